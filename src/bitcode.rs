@@ -0,0 +1,526 @@
+use crate::count::{demangle_symbol, Analysis};
+use std::collections::HashMap as Map;
+use std::io;
+
+// https://llvm.org/docs/BitCodeFormat.html
+const MAGIC: [u8; 4] = [b'B', b'C', 0xc0, 0xde];
+const WRAPPER_MAGIC: [u8; 4] = [0xde, 0xc0, 0x17, 0x0b];
+
+const END_BLOCK: u64 = 0;
+const ENTER_SUBBLOCK: u64 = 1;
+const DEFINE_ABBREV: u64 = 2;
+const UNABBREV_RECORD: u64 = 3;
+
+const BLOCKINFO_BLOCK_ID: u64 = 0;
+const MODULE_BLOCK_ID: u64 = 8;
+const FUNCTION_BLOCK_ID: u64 = 12;
+const STRTAB_BLOCK_ID: u64 = 23;
+
+const BLOCKINFO_CODE_SETBID: u64 = 1;
+const MODULE_CODE_VERSION: u64 = 1;
+const MODULE_CODE_FUNCTION: u64 = 8;
+const STRTAB_BLOB: u64 = 1;
+
+// Records in a function block that are not instructions, and the switch
+// instruction, which spans more than one line.
+const FUNC_CODE_DECLAREBLOCKS: u64 = 1;
+const FUNC_CODE_INST_SWITCH: u64 = 12;
+const FUNC_CODE_DEBUG_LOC_AGAIN: u64 = 33;
+const FUNC_CODE_DEBUG_LOC: u64 = 35;
+const FUNC_CODE_OPERAND_BUNDLE: u64 = 55;
+const FUNC_CODE_BLOCKADDR_USERS: u64 = 60;
+const FUNC_CODE_DEBUG_RECORDS: [u64; 5] = [61, 62, 63, 64, 65];
+
+pub fn is_bitcode(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC) || data.starts_with(&WRAPPER_MAGIC)
+}
+
+/// Counts the instructions of every function defined in a bitcode module,
+/// as produced by `--emit=llvm-bc`, the same way that `count_lines` counts
+/// the lines of their textual IR. Only the function table is populated; the
+/// reports that look at other parts of the module are empty for bitcode.
+pub fn count_bitcode(analysis: &mut Analysis, data: &[u8]) -> io::Result<()> {
+    let data = unwrap_wrapper(data)?;
+    let mut reader = Reader {
+        cursor: Cursor { data, bit: 32 },
+        block_info: Map::new(),
+        module: Module::default(),
+    };
+    while !reader.cursor.at_end() {
+        match reader.cursor.read(2)? {
+            ENTER_SUBBLOCK => reader.block()?,
+            // Padding at the end of the stream.
+            END_BLOCK => break,
+            _ => return Err(malformed()),
+        }
+    }
+
+    analysis.bitcode_modules += 1;
+    let module = reader.module;
+    let strtab = module.strtab.unwrap_or_default();
+    for (function, lines) in module.defined_functions.iter().zip(module.function_lines) {
+        let name = strtab
+            .get(function.offset..function.offset + function.size)
+            .map(String::from_utf8_lossy);
+        let name = match name {
+            Some(symbol) => demangle_symbol(&symbol),
+            None => "(unnamed function)".to_owned(),
+        };
+        analysis
+            .instantiations
+            .entry(name)
+            .or_insert_with(Default::default)
+            .record_lines(lines);
+    }
+    Ok(())
+}
+
+// The optional wrapper header: magic, version, offset, size, cputype.
+fn unwrap_wrapper(data: &[u8]) -> io::Result<&[u8]> {
+    if !data.starts_with(&WRAPPER_MAGIC) {
+        return Ok(data);
+    }
+    let field = |i: usize| {
+        data.get(i * 4..i * 4 + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    let (Some(offset), Some(size)) = (field(2), field(3)) else {
+        return Err(malformed());
+    };
+    data.get(offset..offset + size)
+        .filter(|data| data.starts_with(&MAGIC))
+        .ok_or_else(malformed)
+}
+
+// The number of lines that the record of a function block occupies in the
+// textual IR, as counted by `count_lines`.
+fn instruction_lines(code: u64) -> usize {
+    match code {
+        FUNC_CODE_DECLAREBLOCKS
+        | FUNC_CODE_DEBUG_LOC_AGAIN
+        | FUNC_CODE_DEBUG_LOC
+        | FUNC_CODE_OPERAND_BUNDLE
+        | FUNC_CODE_BLOCKADDR_USERS => 0,
+        _ if FUNC_CODE_DEBUG_RECORDS.contains(&code) => 0,
+        // The switch and the closing `  ]` of its list of cases.
+        FUNC_CODE_INST_SWITCH => 2,
+        _ => 1,
+    }
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed LLVM bitcode")
+}
+
+#[derive(Default)]
+struct Module {
+    version: u64,
+    defined_functions: Vec<StrtabRef>,
+    function_lines: Vec<usize>,
+    strtab: Option<Vec<u8>>,
+}
+
+struct StrtabRef {
+    offset: usize,
+    size: usize,
+}
+
+#[derive(Clone)]
+enum Op {
+    Literal(u64),
+    Fixed(u32),
+    Vbr(u32),
+    Array,
+    Char6,
+    Blob,
+}
+
+struct Record {
+    code: u64,
+    ops: Vec<u64>,
+    blob: Option<Vec<u8>>,
+}
+
+struct Reader<'a> {
+    cursor: Cursor<'a>,
+    // Abbreviations defined in the BLOCKINFO block for every block of the
+    // given id.
+    block_info: Map<u64, Vec<Vec<Op>>>,
+    module: Module,
+}
+
+impl Reader<'_> {
+    // Called after ENTER_SUBBLOCK has been read.
+    fn block(&mut self) -> io::Result<()> {
+        let block_id = self.cursor.read_vbr(8)?;
+        let abbrev_width = u32::try_from(self.cursor.read_vbr(4)?).map_err(|_| malformed())?;
+        self.cursor.align32();
+        let len_words = usize::try_from(self.cursor.read(32)?).map_err(|_| malformed())?;
+
+        let interesting = matches!(
+            block_id,
+            BLOCKINFO_BLOCK_ID | MODULE_BLOCK_ID | FUNCTION_BLOCK_ID | STRTAB_BLOCK_ID
+        );
+        if !interesting || abbrev_width == 0 || abbrev_width > 32 {
+            return self.cursor.skip(len_words * 32);
+        }
+
+        let mut abbrevs = self.block_info.get(&block_id).cloned().unwrap_or_default();
+        let mut blockinfo_target = None;
+        let mut lines = 0;
+        loop {
+            match self.cursor.read(abbrev_width)? {
+                END_BLOCK => {
+                    self.cursor.align32();
+                    break;
+                }
+                ENTER_SUBBLOCK => {
+                    // Nested blocks of a function, such as its constants and
+                    // metadata, are not instructions.
+                    if block_id == FUNCTION_BLOCK_ID {
+                        self.skip_block()?;
+                    } else {
+                        self.block()?;
+                    }
+                }
+                DEFINE_ABBREV => {
+                    let abbrev = self.cursor.read_abbrev()?;
+                    if block_id == BLOCKINFO_BLOCK_ID {
+                        let target = blockinfo_target.ok_or_else(malformed)?;
+                        self.block_info.entry(target).or_default().push(abbrev);
+                    } else {
+                        abbrevs.push(abbrev);
+                    }
+                }
+                abbrev_id => {
+                    let record = if abbrev_id == UNABBREV_RECORD {
+                        self.cursor.read_unabbrev_record()?
+                    } else {
+                        let abbrev = usize::try_from(abbrev_id - 4)
+                            .ok()
+                            .and_then(|i| abbrevs.get(i))
+                            .ok_or_else(malformed)?;
+                        self.cursor.read_abbrev_record(abbrev)?
+                    };
+                    match (block_id, record.code) {
+                        (BLOCKINFO_BLOCK_ID, BLOCKINFO_CODE_SETBID) => {
+                            blockinfo_target = record.ops.first().copied();
+                        }
+                        (MODULE_BLOCK_ID, _) => self.module_record(&record)?,
+                        (FUNCTION_BLOCK_ID, code) => lines += instruction_lines(code),
+                        (STRTAB_BLOCK_ID, STRTAB_BLOB) => self.module.strtab = record.blob,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        if block_id == FUNCTION_BLOCK_ID {
+            self.module.function_lines.push(lines);
+        }
+        Ok(())
+    }
+
+    fn skip_block(&mut self) -> io::Result<()> {
+        let _block_id = self.cursor.read_vbr(8)?;
+        let _abbrev_width = self.cursor.read_vbr(4)?;
+        self.cursor.align32();
+        let len_words = usize::try_from(self.cursor.read(32)?).map_err(|_| malformed())?;
+        self.cursor.skip(len_words * 32)
+    }
+
+    fn module_record(&mut self, record: &Record) -> io::Result<()> {
+        match record.code {
+            MODULE_CODE_VERSION => {
+                self.module.version = record.ops.first().copied().unwrap_or(0);
+            }
+            // [strtab_offset, strtab_size, type, callingconv, isproto, ...]
+            MODULE_CODE_FUNCTION if self.module.version >= 2 => {
+                if let [offset, size, _type, _cc, isproto, ..] = record.ops[..] {
+                    if isproto == 0 {
+                        self.module.defined_functions.push(StrtabRef {
+                            offset: usize::try_from(offset).map_err(|_| malformed())?,
+                            size: usize::try_from(size).map_err(|_| malformed())?,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl Cursor<'_> {
+    fn at_end(&self) -> bool {
+        self.bit >= self.data.len() * 8
+    }
+
+    fn read(&mut self, width: u32) -> io::Result<u64> {
+        if width > 64 {
+            return Err(malformed());
+        }
+        let mut value = 0;
+        let mut got = 0;
+        while got < width {
+            let byte = *self.data.get(self.bit / 8).ok_or_else(malformed)?;
+            let offset = u32::try_from(self.bit % 8).map_err(|_| malformed())?;
+            let take = (8 - offset).min(width - got);
+            let bits = (u64::from(byte) >> offset) & ((1 << take) - 1);
+            value |= bits << got;
+            got += take;
+            self.bit += take as usize;
+        }
+        Ok(value)
+    }
+
+    fn read_vbr(&mut self, width: u32) -> io::Result<u64> {
+        if !(2..=32).contains(&width) {
+            return Err(malformed());
+        }
+        let continuation = 1 << (width - 1);
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let chunk = self.read(width)?;
+            if shift >= 64 {
+                return Err(malformed());
+            }
+            value |= (chunk & (continuation - 1)) << shift;
+            if chunk & continuation == 0 {
+                return Ok(value);
+            }
+            shift += width - 1;
+        }
+    }
+
+    fn align32(&mut self) {
+        self.bit = self.bit.next_multiple_of(32);
+    }
+
+    fn skip(&mut self, bits: usize) -> io::Result<()> {
+        self.bit += bits;
+        if self.bit > self.data.len() * 8 {
+            return Err(malformed());
+        }
+        Ok(())
+    }
+
+    fn read_abbrev(&mut self) -> io::Result<Vec<Op>> {
+        let num_ops = self.read_vbr(5)?;
+        let mut ops = Vec::new();
+        for _ in 0..num_ops {
+            let is_literal = self.read(1)? == 1;
+            let op = if is_literal {
+                Op::Literal(self.read_vbr(8)?)
+            } else {
+                match self.read(3)? {
+                    1 => Op::Fixed(u32::try_from(self.read_vbr(5)?).map_err(|_| malformed())?),
+                    2 => Op::Vbr(u32::try_from(self.read_vbr(5)?).map_err(|_| malformed())?),
+                    3 => Op::Array,
+                    4 => Op::Char6,
+                    5 => Op::Blob,
+                    _ => return Err(malformed()),
+                }
+            };
+            ops.push(op);
+        }
+        Ok(ops)
+    }
+
+    fn read_unabbrev_record(&mut self) -> io::Result<Record> {
+        let code = self.read_vbr(6)?;
+        let num_ops = self.read_vbr(6)?;
+        let mut ops = Vec::new();
+        for _ in 0..num_ops {
+            ops.push(self.read_vbr(6)?);
+        }
+        Ok(Record {
+            code,
+            ops,
+            blob: None,
+        })
+    }
+
+    fn read_abbrev_record(&mut self, abbrev: &[Op]) -> io::Result<Record> {
+        let mut values = Vec::new();
+        let mut blob = None;
+        let mut ops = abbrev.iter();
+        while let Some(op) = ops.next() {
+            match op {
+                Op::Array => {
+                    let element = ops.next().ok_or_else(malformed)?;
+                    let len = self.read_vbr(6)?;
+                    for _ in 0..len {
+                        values.push(self.read_scalar(element)?);
+                    }
+                }
+                Op::Blob => {
+                    let len = usize::try_from(self.read_vbr(6)?).map_err(|_| malformed())?;
+                    self.align32();
+                    let start = self.bit / 8;
+                    let bytes = self.data.get(start..start + len).ok_or_else(malformed)?;
+                    blob = Some(bytes.to_vec());
+                    self.bit += len * 8;
+                    self.align32();
+                }
+                scalar => values.push(self.read_scalar(scalar)?),
+            }
+        }
+        if values.is_empty() {
+            return Err(malformed());
+        }
+        let code = values.remove(0);
+        Ok(Record {
+            code,
+            ops: values,
+            blob,
+        })
+    }
+
+    fn read_scalar(&mut self, op: &Op) -> io::Result<u64> {
+        match *op {
+            Op::Literal(value) => Ok(value),
+            Op::Fixed(0) | Op::Vbr(0) => Ok(0),
+            Op::Fixed(width) => self.read(width),
+            Op::Vbr(width) => self.read_vbr(width),
+            Op::Char6 => self.read(6),
+            Op::Array | Op::Blob => Err(malformed()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bit: usize,
+}
+
+#[cfg(test)]
+impl BitWriter {
+    fn fixed(&mut self, width: u32, value: u64) {
+        for i in 0..width {
+            if self.bit % 8 == 0 {
+                self.bytes.push(0);
+            }
+            if value >> i & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 1 << (self.bit % 8);
+            }
+            self.bit += 1;
+        }
+    }
+
+    fn vbr(&mut self, width: u32, mut value: u64) {
+        let continuation = 1 << (width - 1);
+        while value >= continuation {
+            self.fixed(width, value & (continuation - 1) | continuation);
+            value >>= width - 1;
+        }
+        self.fixed(width, value);
+    }
+
+    fn align32(&mut self) {
+        while self.bit % 32 != 0 {
+            self.fixed(1, 0);
+        }
+    }
+
+    // Returns the position of the block length, to be filled in by `end`.
+    fn enter(&mut self, abbrev_width: u32, block_id: u64, new_abbrev_width: u32) -> usize {
+        self.fixed(abbrev_width, ENTER_SUBBLOCK);
+        self.vbr(8, block_id);
+        self.vbr(4, u64::from(new_abbrev_width));
+        self.align32();
+        let len_pos = self.bytes.len();
+        self.fixed(32, 0);
+        len_pos
+    }
+
+    fn end(&mut self, abbrev_width: u32, len_pos: usize) {
+        self.fixed(abbrev_width, END_BLOCK);
+        self.align32();
+        let len_words = u32::try_from((self.bytes.len() - len_pos - 4) / 4).unwrap();
+        self.bytes[len_pos..len_pos + 4].copy_from_slice(&len_words.to_le_bytes());
+    }
+
+    fn record(&mut self, abbrev_width: u32, code: u64, ops: &[u64]) {
+        self.fixed(abbrev_width, UNABBREV_RECORD);
+        self.vbr(6, code);
+        self.vbr(6, ops.len() as u64);
+        for &op in ops {
+            self.vbr(6, op);
+        }
+    }
+}
+
+#[test]
+fn test_count_bitcode() {
+    const FUNC_CODE_INST_BINOP: u64 = 2;
+    const FUNC_CODE_INST_RET: u64 = 10;
+    const CONSTANTS_BLOCK_ID: u64 = 11;
+
+    let h = "_ZN1a1h17h0123456789abcdefE";
+    let strtab = format!("fg{}", h);
+
+    let mut w = BitWriter::default();
+    w.fixed(32, u64::from(u32::from_le_bytes(MAGIC)));
+
+    let module = w.enter(2, MODULE_BLOCK_ID, 3);
+    w.record(3, MODULE_CODE_VERSION, &[2]);
+    // [strtab_offset, strtab_size, type, callingconv, isproto]
+    w.record(3, MODULE_CODE_FUNCTION, &[0, 1, 0, 0, 0]);
+    w.record(3, MODULE_CODE_FUNCTION, &[1, 1, 0, 0, 1]);
+    w.record(3, MODULE_CODE_FUNCTION, &[2, h.len() as u64, 0, 0, 0]);
+
+    let f = w.enter(3, FUNCTION_BLOCK_ID, 4);
+    w.record(4, FUNC_CODE_DECLAREBLOCKS, &[1]);
+    let constants = w.enter(4, CONSTANTS_BLOCK_ID, 4);
+    w.record(4, 1, &[0]);
+    w.end(4, constants);
+    w.record(4, FUNC_CODE_INST_BINOP, &[1, 2, 0]);
+    w.record(4, FUNC_CODE_DEBUG_LOC, &[1, 1, 0, 0]);
+    w.record(4, FUNC_CODE_INST_RET, &[]);
+    w.end(4, f);
+
+    let h = w.enter(3, FUNCTION_BLOCK_ID, 4);
+    w.record(4, FUNC_CODE_DECLAREBLOCKS, &[3]);
+    w.record(4, FUNC_CODE_INST_SWITCH, &[0, 1, 2, 0, 1]);
+    w.record(4, FUNC_CODE_INST_RET, &[]);
+    w.record(4, FUNC_CODE_INST_RET, &[]);
+    w.end(4, h);
+    w.end(3, module);
+
+    // An abbreviation for the string table blob: [STRTAB_BLOB, blob].
+    let strtab_block = w.enter(2, STRTAB_BLOCK_ID, 3);
+    w.fixed(3, DEFINE_ABBREV);
+    w.vbr(5, 2);
+    w.fixed(1, 1);
+    w.vbr(8, STRTAB_BLOB);
+    w.fixed(1, 0);
+    w.fixed(3, 5);
+    w.fixed(3, 4);
+    w.vbr(6, strtab.len() as u64);
+    w.align32();
+    for byte in strtab.bytes() {
+        w.fixed(8, u64::from(byte));
+    }
+    w.align32();
+    w.end(3, strtab_block);
+
+    assert!(is_bitcode(&w.bytes));
+    let mut analysis = Analysis::default();
+    count_bitcode(&mut analysis, &w.bytes).unwrap();
+    let mut functions: Vec<_> = analysis
+        .instantiations
+        .iter()
+        .map(|(name, x)| (name.as_str(), x.copies, x.total_lines))
+        .collect();
+    functions.sort_unstable();
+    assert_eq!(functions, [("a::h", 1, 4), ("f", 1, 2)]);
+}
@@ -0,0 +1,117 @@
+use crate::count::Instantiations;
+use crate::error::{Error, Result};
+use regex::Regex;
+use std::collections::HashMap as Map;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug)]
+pub struct Bucket {
+    pub name: String,
+    pub regex: Regex,
+}
+
+impl FromStr for Bucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let Some((name, regex)) = s.split_once('=') else {
+            return Err("expected NAME=REGEX".to_owned());
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("bucket name must not be empty".to_owned());
+        }
+        match Regex::new(regex.trim()) {
+            Ok(regex) => Ok(Bucket {
+                name: name.to_owned(),
+                regex,
+            }),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// Reads one `NAME=REGEX` bucket per line, skipping blank lines and lines
+/// starting with `#`.
+pub fn read_bucket_file(path: &Path) -> Result<Vec<Bucket>> {
+    let content = fs::read_to_string(path).map_err(|err| Error::PathIo(path.to_owned(), err))?;
+
+    let mut buckets = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse() {
+            Ok(bucket) => buckets.push(bucket),
+            Err(msg) => return Err(Error::PathLine(path.to_owned(), i + 1, msg)),
+        }
+    }
+    Ok(buckets)
+}
+
+/// Totals the functions by the first bucket whose regex matches the function
+/// name, or "(other)" if none match.
+pub fn bucket_totals(
+    instantiations: &Map<String, Instantiations>,
+    buckets: &[Bucket],
+) -> Map<String, Instantiations> {
+    let mut totals = Map::<String, Instantiations>::new();
+    for (name, instantiations) in instantiations {
+        let bucket = buckets
+            .iter()
+            .find(|bucket| bucket.regex.is_match(name))
+            .map_or("(other)", |bucket| &bucket.name);
+        totals
+            .entry(bucket.to_owned())
+            .or_insert_with(Default::default)
+            .merge(instantiations);
+    }
+    totals
+}
+
+#[test]
+fn test_parse_buckets() {
+    let bucket: Bucket = " fmt = ^core::fmt:: ".parse().unwrap();
+    assert_eq!(bucket.name, "fmt");
+    assert_eq!(bucket.regex.as_str(), "^core::fmt::");
+    assert!("fmt".parse::<Bucket>().is_err());
+    assert!("=^core::fmt::".parse::<Bucket>().is_err());
+    assert!("fmt=(".parse::<Bucket>().is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("buckets");
+    fs::write(&path, "# by module\n\nfmt=^core::fmt::\nserde=serde::\n").unwrap();
+    let buckets = read_bucket_file(&path).unwrap();
+    let names: Vec<&str> = buckets.iter().map(|bucket| bucket.name.as_str()).collect();
+    assert_eq!(names, ["fmt", "serde"]);
+
+    fs::write(&path, "fmt=^core::fmt::\n\nserde\n").unwrap();
+    let err = read_bucket_file(&path).unwrap_err();
+    assert!(matches!(err, Error::PathLine(_, 3, _)));
+}
+
+#[test]
+fn test_bucket_totals() {
+    let buckets = ["fmt=::fmt::", "debug=Debug"].map(|bucket| bucket.parse().unwrap());
+    let mut instantiations = Map::new();
+    for (name, lines) in [
+        ("core::fmt::write", 10),
+        ("<a::Thing as core::fmt::Debug>::fmt", 20),
+        ("a::DebugList::entry", 5),
+        ("a::main", 3),
+    ] {
+        let mut function = Instantiations::default();
+        function.record_lines(lines);
+        instantiations.insert(name.to_owned(), function);
+    }
+
+    let totals = bucket_totals(&instantiations, &buckets);
+    assert_eq!(totals.len(), 3);
+    // The first matching bucket wins.
+    assert_eq!(totals["fmt"].total_lines, 30);
+    assert_eq!(totals["debug"].total_lines, 5);
+    assert_eq!(totals["(other)"].total_lines, 3);
+}
@@ -0,0 +1,113 @@
+use crate::closure::enclosing_function;
+use crate::count::Instantiations;
+use crate::future::Futures;
+use crate::name::strip_crate_disambiguators;
+use std::collections::HashMap as Map;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Category {
+    DropGlue,
+    TraitObjectShims,
+    Async,
+    Formatting,
+    Panicking,
+    Serde,
+    IteratorAdapters,
+    Allocation,
+    Closures,
+    Other,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::DropGlue => "drop glue",
+            Category::TraitObjectShims => "trait object shims",
+            Category::Async => "async",
+            Category::Formatting => "formatting",
+            Category::Panicking => "panicking",
+            Category::Serde => "serde",
+            Category::IteratorAdapters => "iterator adapters",
+            Category::Allocation => "allocation",
+            Category::Closures => "closures",
+            Category::Other => "other",
+        }
+    }
+}
+
+/// Totals the functions by the first category, in the order of `Category`'s
+/// variants, that each one falls into.
+pub fn categorize_all(
+    instantiations: &Map<String, Instantiations>,
+    futures: &Futures,
+) -> Map<Category, Instantiations> {
+    let mut categories = Map::<Category, Instantiations>::new();
+    for (name, instantiations) in instantiations {
+        categories
+            .entry(categorize(name, futures))
+            .or_insert_with(Default::default)
+            .merge(instantiations);
+    }
+    categories
+}
+
+pub fn categorize(name: &str, futures: &Futures) -> Category {
+    if futures.is_poll_function(name) {
+        return Category::Async;
+    }
+
+    let name = strip_crate_disambiguators(name);
+    let name = name.as_str();
+    if name.contains("::ptr::drop_in_place") || name.contains(" as core::ops::drop::Drop>::drop") {
+        Category::DropGlue
+    } else if name.contains("{{vtable.shim}}") || name.contains("{shim:vtable") {
+        Category::TraitObjectShims
+    } else if name.contains("core::future::") || name.contains("core::task::") {
+        Category::Async
+    } else if name.contains("core::fmt::") || name.contains("alloc::fmt::") {
+        Category::Formatting
+    } else if name.contains("core::panicking::")
+        || name.contains("std::panicking::")
+        || name.contains("::panic_const::")
+        || name.contains("::unwrap_failed")
+        || name.contains("::expect_failed")
+    {
+        Category::Panicking
+    } else if name.contains("serde::") || name.contains("serde_json::") {
+        Category::Serde
+    } else if name.contains("core::iter::") || name.contains("::spec_from_iter") {
+        Category::IteratorAdapters
+    } else if name.contains("alloc::alloc::")
+        || name.contains("alloc::raw_vec::")
+        || name.contains("core::alloc::")
+    {
+        Category::Allocation
+    } else if enclosing_function(name).is_some() {
+        Category::Closures
+    } else {
+        Category::Other
+    }
+}
+
+#[test]
+fn test_categorize() {
+    let futures = Futures::default();
+    let cases = [
+        ("core::panicking::panic_fmt", Category::Panicking),
+        (
+            "core::panicking::panic_const::panic_const_add_overflow",
+            Category::Panicking,
+        ),
+        ("std::panicking::begin_panic::<&str>", Category::Panicking),
+        ("core::result::unwrap_failed", Category::Panicking),
+        (
+            "std::panic::catch_unwind::<a::f::{{closure}}, ()>",
+            Category::Other,
+        ),
+        ("a::no_panic::check", Category::Other),
+        ("<b::S as b::T>::m::{{closure}}", Category::Closures),
+    ];
+    for (name, expected) in cases {
+        assert!(categorize(name, &futures) == expected, "{}", name);
+    }
+}
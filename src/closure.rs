@@ -0,0 +1,103 @@
+use crate::count::Instantiations;
+use crate::name::split_trait_impl;
+use std::collections::HashMap as Map;
+
+#[derive(Default)]
+pub struct Folded {
+    /// The enclosing function together with all of its closures.
+    pub total: Instantiations,
+    /// Just the closures' share of `total`.
+    pub closures: Instantiations,
+}
+
+/// Attributes every closure, and the trait impls the compiler generates for
+/// it such as `call_once`, to the function in which the closure is written.
+pub fn fold_closures(instantiations: Map<String, Instantiations>) -> Map<String, Folded> {
+    let mut folded = Map::<String, Folded>::new();
+
+    for (name, instantiations) in instantiations {
+        match enclosing_function(&name) {
+            Some(enclosing) => {
+                let entry = folded
+                    .entry(enclosing.to_owned())
+                    .or_insert_with(Default::default);
+                entry.total.merge(&instantiations);
+                entry.closures.merge(&instantiations);
+            }
+            None => folded
+                .entry(name)
+                .or_insert_with(Default::default)
+                .total
+                .merge(&instantiations),
+        }
+    }
+
+    folded
+}
+
+pub fn enclosing_function(name: &str) -> Option<&str> {
+    // <a::main::{{closure}} as core::ops::function::FnOnce<()>>::call_once
+    if let Some((self_ty, _trait, _method)) = split_trait_impl(name) {
+        if let Some(enclosing) = strip_closures(self_ty) {
+            return Some(enclosing);
+        }
+    }
+    strip_closures(name)
+}
+
+// legacy: a::main::{{closure}}::{{closure}}
+// v0:     a[4c2faf61c2d5dfd7]::main::{closure#0}::{closure#1}
+fn strip_closures(mut name: &str) -> Option<&str> {
+    let original_len = name.len();
+    loop {
+        if let Some(rest) = name.strip_suffix("::{{closure}}") {
+            name = rest;
+        } else if let Some((rest, index)) = name.rsplit_once("::{closure#") {
+            let is_closure = index.strip_suffix('}').is_some_and(|index| {
+                !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit())
+            });
+            if !is_closure {
+                break;
+            }
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    (name.len() < original_len).then_some(name)
+}
+
+#[test]
+fn test_enclosing_function() {
+    let cases = [
+        ("a::main", None),
+        ("a::main::{{closure}}", Some("a::main")),
+        ("a::main::{{closure}}::{{closure}}", Some("a::main")),
+        (
+            "a[4c2faf61c2d5dfd7]::main::{closure#0}::{closure#1}",
+            Some("a[4c2faf61c2d5dfd7]::main"),
+        ),
+        ("<b::S as b::T>::m::{{closure}}", Some("<b::S as b::T>::m")),
+        (
+            "<b[1c2d]::S as b[1c2d]::T>::m::{closure#0}",
+            Some("<b[1c2d]::S as b[1c2d]::T>::m"),
+        ),
+        (
+            "<u32 as core::iter::traits::accum::Sum>::sum::{{closure}}::{{closure}}",
+            Some("<u32 as core::iter::traits::accum::Sum>::sum"),
+        ),
+        (
+            "<a::main::{{closure}} as core::ops::function::FnOnce<()>>::call_once",
+            Some("a::main"),
+        ),
+        (
+            "<a[4c2f]::main::{closure#0} as core[9b1e]::ops::function::FnOnce<()>>::call_once",
+            Some("a[4c2f]::main"),
+        ),
+        ("<b::S as b::T>::m", None),
+        ("a::main::{closure#x}", None),
+    ];
+    for (name, expected) in cases {
+        assert_eq!(enclosing_function(name), expected, "{}", name);
+    }
+}
@@ -1,50 +1,345 @@
+use crate::duplicate::Duplicates;
+use crate::future::Futures;
+use crate::global::Globals;
+use crate::lex;
+use crate::vtable::Vtables;
 use rustc_demangle::demangle;
+use std::borrow::Cow;
 use std::collections::HashMap as Map;
+use std::io::{self, BufRead};
 
 #[derive(Default)]
+pub struct Analysis {
+    pub instantiations: Map<String, Instantiations>,
+    pub composition: Composition,
+    pub globals: Globals,
+    pub vtables: Vtables,
+    pub futures: Futures,
+    pub duplicates: Duplicates,
+    // Modules read from bitcode, for which only the function table is
+    // populated.
+    pub bitcode_modules: usize,
+}
+
+impl Analysis {
+    /// Combines the analysis of another, separately compiled module into this
+    /// one.
+    pub fn merge(&mut self, other: Analysis) {
+        for (name, instantiations) in other.instantiations {
+            self.instantiations
+                .entry(name)
+                .or_insert_with(Default::default)
+                .merge(&instantiations);
+        }
+        self.composition.merge(&other.composition);
+        self.globals.merge(other.globals);
+        self.vtables.merge(other.vtables);
+        self.futures.merge(other.futures);
+        self.duplicates.merge(other.duplicates);
+        self.bitcode_modules += other.bitcode_modules;
+    }
+
+    pub fn total(&self) -> Instantiations {
+        let mut total = Instantiations::default();
+        for instantiations in self.instantiations.values() {
+            total.merge(instantiations);
+        }
+        total
+    }
+}
+
+#[derive(Default, Clone)]
 pub struct Instantiations {
     pub copies: usize,
     pub total_lines: usize,
 }
 
 impl Instantiations {
-    fn record_lines(&mut self, lines: usize) {
+    pub fn record_lines(&mut self, lines: usize) {
         self.copies += 1;
         self.total_lines += lines;
     }
+
+    pub fn merge(&mut self, other: &Instantiations) {
+        self.copies += other.copies;
+        self.total_lines += other.total_lines;
+    }
+}
+
+/// Breakdown of every line of the .ll file, not only those inside function
+/// bodies, by the kind of top-level entity it belongs to.
+#[derive(Default)]
+pub struct Composition {
+    pub function_bodies: Section,
+    pub declarations: Section,
+    pub global_data: Section,
+    pub type_definitions: Section,
+    pub attribute_groups: Section,
+    pub debug_metadata: Section,
+    pub other: Section,
+}
+
+#[derive(Default, Copy, Clone)]
+pub struct Section {
+    pub lines: usize,
+    pub bytes: usize,
+}
+
+impl Section {
+    fn record_line(&mut self, line: &str) {
+        self.lines += 1;
+        // Plus one for the newline.
+        self.bytes += line.len() + 1;
+    }
+
+    fn merge(&mut self, other: &Section) {
+        self.lines += other.lines;
+        self.bytes += other.bytes;
+    }
+}
+
+impl Composition {
+    pub fn sections(&self) -> [(&'static str, Section); 7] {
+        [
+            ("Function bodies", self.function_bodies),
+            ("Declarations", self.declarations),
+            ("Global data", self.global_data),
+            ("Type definitions", self.type_definitions),
+            ("Attribute groups", self.attribute_groups),
+            ("Debug metadata", self.debug_metadata),
+            ("Other", self.other),
+        ]
+    }
+
+    fn record_toplevel_line(&mut self, line: &str) {
+        let section = if line.starts_with("declare ") {
+            &mut self.declarations
+        } else if line.starts_with('@') {
+            &mut self.global_data
+        } else if line.starts_with('%') && line.contains(" = type ") {
+            &mut self.type_definitions
+        } else if line.starts_with("attributes #") {
+            &mut self.attribute_groups
+        } else if line.starts_with('!') {
+            &mut self.debug_metadata
+        } else {
+            &mut self.other
+        };
+        section.record_line(line);
+    }
+
+    fn merge(&mut self, other: &Composition) {
+        let Composition {
+            function_bodies,
+            declarations,
+            global_data,
+            type_definitions,
+            attribute_groups,
+            debug_metadata,
+            other,
+        } = other;
+        self.function_bodies.merge(function_bodies);
+        self.declarations.merge(declarations);
+        self.global_data.merge(global_data);
+        self.type_definitions.merge(type_definitions);
+        self.attribute_groups.merge(attribute_groups);
+        self.debug_metadata.merge(debug_metadata);
+        self.other.merge(other);
+    }
+}
+
+/// Problems in the IR that caused functions to be skipped, with the line
+/// numbers at which they were found.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub warnings: Vec<(usize, String)>,
+    pub skipped_functions: usize,
+}
+
+impl Diagnostics {
+    fn skip(&mut self, line: usize, message: String) {
+        self.warnings.push((line, message));
+        self.skipped_functions += 1;
+    }
 }
 
-pub fn count_lines(instantiations: &mut Map<String, Instantiations>, ir: &[u8]) {
-    let mut current_function = None;
-    let mut count = 0;
+/// Reads the IR one line at a time, so that the memory used does not depend
+/// on the size of the input.
+pub fn count_lines(analysis: &mut Analysis, mut ir: impl BufRead) -> io::Result<Diagnostics> {
+    let mut diagnostics = Diagnostics::default();
+    let mut header = None::<(usize, String)>;
+    let mut body = None::<Body>;
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    let mut invalid_utf8 = false;
 
-    for line in String::from_utf8_lossy(ir).lines() {
-        if line.starts_with("define ") {
-            current_function = parse_function_name(line);
-        } else if line == "}" {
-            if let Some(name) = current_function.take() {
-                instantiations
-                    .entry(name)
-                    .or_insert_with(Default::default)
-                    .record_lines(count);
+    loop {
+        buf.clear();
+        if ir.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        line_number += 1;
+        let line = String::from_utf8_lossy(&buf);
+        if let Cow::Owned(_) = line {
+            if !invalid_utf8 {
+                invalid_utf8 = true;
+                diagnostics.warnings.push((
+                    line_number,
+                    "invalid UTF-8, which was replaced by U+FFFD".to_owned(),
+                ));
             }
-            count = 0;
-        } else if line.starts_with("  ") && !line.starts_with("   ") {
-            count += 1;
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+
+        if let Some(function) = &mut body {
+            if line.starts_with("define ") {
+                diagnostics.skip(
+                    function.start,
+                    format!("skipped {} which has no closing `}}`", function.describe()),
+                );
+                analysis.futures.discard_function();
+                body = None;
+            } else {
+                analysis.composition.function_bodies.record_line(line);
+                function.depth += lex::brace_delta(line);
+                if function.depth <= 0 {
+                    let function = body.take().unwrap();
+                    record_function(analysis, function);
+                    continue;
+                }
+                if line.starts_with("  ") && !line.starts_with("   ") {
+                    function.lines += 1;
+                }
+                analysis.futures.record_body_line(line);
+                continue;
+            }
+        }
+
+        if header.is_some() || line.starts_with("define ") {
+            analysis.composition.function_bodies.record_line(line);
+            if line.starts_with("define ") {
+                if let Some((start, _text)) = header.take() {
+                    diagnostics.skip(
+                        start,
+                        "skipped a `define` whose body never begins".to_owned(),
+                    );
+                }
+            }
+            let (start, text) = header.get_or_insert_with(|| (line_number, String::new()));
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(line);
+            let (start, text) = (*start, &*text);
+            if let lex::Header::Complete { symbol, rest } = lex::scan_define(text) {
+                if symbol.is_none() {
+                    diagnostics.skip(
+                        start,
+                        "skipped a function whose name could not be parsed".to_owned(),
+                    );
+                }
+                analysis.duplicates.record_define(text);
+                let function = Body {
+                    symbol,
+                    start,
+                    depth: 1 + lex::brace_delta(rest),
+                    lines: 0,
+                };
+                header = None;
+                if function.depth <= 0 {
+                    record_function(analysis, function);
+                } else {
+                    body = Some(function);
+                }
+            }
+            continue;
+        }
+
+        if !line.is_empty() {
+            analysis.composition.record_toplevel_line(line);
+            if line.starts_with('@') {
+                analysis.globals.record_global(line);
+                if line.starts_with("@vtable.") {
+                    analysis.vtables.record_vtable(line);
+                }
+            } else if line.starts_with('!') {
+                analysis.vtables.record_metadata(line);
+                analysis.futures.record_metadata(line);
+                analysis.duplicates.record_metadata(line);
+            } else if line.starts_with('%') {
+                analysis.globals.record_type_definition(line);
+            } else if line.starts_with("target datalayout ") {
+                analysis.globals.record_datalayout(line);
+            } else if line.starts_with("attributes #") {
+                analysis.duplicates.record_attributes(line);
+            }
+        }
+    }
+
+    if let Some(function) = body {
+        diagnostics.skip(
+            function.start,
+            format!("skipped {} which has no closing `}}`", function.describe()),
+        );
+        analysis.futures.discard_function();
+    }
+    if let Some((start, _text)) = header {
+        diagnostics.skip(
+            start,
+            "skipped a `define` whose body never begins".to_owned(),
+        );
+    }
+
+    diagnostics.warnings.sort_by_key(|(line, _message)| *line);
+    analysis.vtables.finish_module();
+    analysis.duplicates.finish_module();
+    Ok(diagnostics)
+}
+
+// A function whose `define` header has been read, up to the end of its body.
+struct Body {
+    symbol: Option<String>,
+    // Line number of the `define`.
+    start: usize,
+    // Nesting of braces, starting with the one that opens the body.
+    depth: isize,
+    lines: usize,
+}
+
+impl Body {
+    fn describe(&self) -> String {
+        match &self.symbol {
+            Some(symbol) => format!("`{}`", demangle_symbol(symbol)),
+            None => "a function".to_owned(),
         }
     }
 }
 
-fn parse_function_name(line: &str) -> Option<String> {
-    let start = line.find('@')? + 1;
-    let end = line[start..].find('(')?;
-    let mangled = line[start..start + end].trim_matches('"');
+fn record_function(analysis: &mut Analysis, function: Body) {
+    let Some(symbol) = function.symbol else {
+        analysis.futures.discard_function();
+        return;
+    };
+    let lines = function.lines;
+    analysis.vtables.record_function(&symbol, lines);
+    analysis.futures.record_function(&symbol, lines);
+    let name = demangle_symbol(&symbol);
+    analysis.duplicates.record_function(&symbol, &name, lines);
+    analysis
+        .instantiations
+        .entry(name)
+        .or_insert_with(Default::default)
+        .record_lines(lines);
+}
+
+pub fn demangle_symbol(mangled: &str) -> String {
     let mut name = demangle(mangled).to_string();
     if has_hash(&name) {
         let len = name.len() - 19;
         name.truncate(len);
     }
-    Some(name)
+    name
 }
 
 fn has_hash(name: &str) -> bool {
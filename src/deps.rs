@@ -0,0 +1,176 @@
+use crate::count::Analysis;
+use crate::error::Result;
+use crate::input;
+use std::collections::HashMap as Map;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Set in the environment of cargo when analyzing dependencies. Cargo runs
+/// cargo-llvm-lines itself as rustc, which finds the directory in which to
+/// collect every crate's IR in this variable.
+pub const DEPS_DIR: &str = "CARGO_LLVM_LINES_DEPS_DIR";
+
+/// The user's own `RUSTC`, if any, to be run by ours.
+pub const RUSTC: &str = "CARGO_LLVM_LINES_RUSTC";
+
+pub fn wrap_rustc(deps_dir: &Path) -> Result<i32> {
+    // Cargo runs any rustc wrapper of the user's, whether from the
+    // environment or from its config, with this executable in place of
+    // rustc, so only the real rustc is left to be run here.
+    let rustc = env::var_os(RUSTC)
+        .filter(|rustc| !rustc.is_empty())
+        .unwrap_or_else(|| OsString::from("rustc"));
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+
+    let mut cmd = Command::new(rustc);
+    cmd.args(&args);
+
+    // Queries such as `rustc -vV` do not compile a crate.
+    let crate_name = flag_value(&args, "--crate-name");
+    let out_dir = flag_value(&args, "--out-dir");
+    let (Some(crate_name), Some(out_dir)) = (crate_name, out_dir) else {
+        return Ok(cmd.status()?.code().unwrap_or(1));
+    };
+    let extra_filename = codegen_option(&args, "extra-filename").unwrap_or_default();
+
+    // The same flags that cargo-llvm-lines passes to the final crate.
+    cmd.arg("--emit=llvm-ir");
+    cmd.arg("-Cno-prepopulate-passes");
+    cmd.arg("-Cpasses=name-anon-globals");
+    let code = cmd.status()?.code().unwrap_or(1);
+    if code != 0 {
+        return Ok(code);
+    }
+
+    // With more than one codegen unit, rustc writes one file per unit:
+    // {crate}{extra}.ll or {crate}{extra}.{crate}.{hash}-cgu.0.rcgu.ll
+    let stem = format!("{}{}", crate_name, extra_filename);
+    let crate_dir = deps_dir.join(&stem);
+    fs::create_dir_all(&crate_dir)?;
+    fs::write(
+        deps_dir.join(format!("{}.name", stem)),
+        display_name(crate_name),
+    )?;
+    for entry in fs::read_dir(&out_dir)? {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+        let is_ir = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ll"));
+        if is_ir && file_name.starts_with(&format!("{}.", stem)) {
+            fs::rename(&path, crate_dir.join(file_name))?;
+        }
+    }
+
+    Ok(0)
+}
+
+// Every build script is compiled as a crate called build_script_build, so
+// they are told apart by the package that cargo compiles them for.
+fn display_name(crate_name: String) -> String {
+    if !crate_name.starts_with("build_script_") {
+        return crate_name;
+    }
+    match env::var("CARGO_PKG_NAME") {
+        Ok(package) => format!("{} ({})", crate_name, package),
+        Err(_) => crate_name,
+    }
+}
+
+fn flag_value(args: &[OsString], flag: &str) -> Option<String> {
+    let i = args.iter().position(|arg| arg == flag)?;
+    args.get(i + 1)?.to_str().map(str::to_owned)
+}
+
+// -C extra-filename=-1b6bc2a04a1f1bd4
+fn codegen_option(args: &[OsString], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let mut args = args.iter().filter_map(|arg| arg.to_str());
+    while let Some(arg) = args.next() {
+        let option = if arg == "-C" {
+            args.next()
+        } else {
+            arg.strip_prefix("-C")
+        };
+        if let Some(value) = option.and_then(|option| option.strip_prefix(&prefix)) {
+            return Some(value.to_owned());
+        }
+    }
+    None
+}
+
+/// Reads the IR collected by the rustc wrapper, keyed by the crate name that
+/// was passed to rustc. Crates that were compiled more than once, such as
+/// with different features, are combined.
+pub fn read_deps_dir(deps_dir: &Path) -> Result<Map<String, Analysis>> {
+    let mut crates = Map::<String, Analysis>::new();
+    let mut crate_dirs = read_dir_sorted(deps_dir)?;
+    crate_dirs.retain(|path| path.is_dir());
+    for crate_dir in crate_dirs {
+        let crate_name = fs::read_to_string(crate_dir.with_extension("name"))?;
+        let analysis = crates.entry(crate_name).or_default();
+        for path in read_dir_sorted(&crate_dir)? {
+            input::count_input(analysis, &path)?;
+        }
+    }
+    Ok(crates)
+}
+
+fn read_dir_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        paths.push(entry?.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[test]
+fn test_codegen_option() {
+    let args = [
+        "--crate-name",
+        "a",
+        "-C",
+        "metadata=1b6b",
+        "-Cextra-filename=-1b6b",
+    ];
+    let args: Vec<OsString> = args.into_iter().map(OsString::from).collect();
+    assert_eq!(codegen_option(&args, "metadata").as_deref(), Some("1b6b"));
+    assert_eq!(
+        codegen_option(&args, "extra-filename").as_deref(),
+        Some("-1b6b")
+    );
+    assert_eq!(flag_value(&args, "--crate-name").as_deref(), Some("a"));
+}
+
+#[test]
+fn test_read_deps_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let crates = [
+        ("build_script_build-1b6b", "build_script_build (a)"),
+        ("build_script_build-9f0e", "build_script_build (b)"),
+        ("serde-32c1", "serde"),
+        ("serde-d4a7", "serde"),
+    ];
+    for (stem, name) in crates {
+        let crate_dir = dir.path().join(stem);
+        fs::create_dir(&crate_dir).unwrap();
+        fs::write(dir.path().join(format!("{}.name", stem)), name).unwrap();
+        let ir = "define void @f() {\n  ret void\n}\n";
+        fs::write(crate_dir.join(format!("{}.ll", stem)), ir).unwrap();
+    }
+
+    let crates = read_deps_dir(dir.path()).unwrap();
+    let mut names: Vec<&str> = crates.keys().map(String::as_str).collect();
+    names.sort_unstable();
+    assert_eq!(
+        names,
+        ["build_script_build (a)", "build_script_build (b)", "serde"],
+    );
+    assert_eq!(crates["serde"].total().copies, 2);
+}
@@ -0,0 +1,58 @@
+use crate::count::Instantiations;
+use crate::name::strip_crate_disambiguators;
+use std::collections::HashMap as Map;
+
+/// Totals the drop glue functions, `core::ptr::drop_in_place<T>`, by the type
+/// `T` being dropped.
+pub fn drop_glue_by_type(
+    instantiations: &Map<String, Instantiations>,
+) -> Map<String, Instantiations> {
+    let mut by_type = Map::<String, Instantiations>::new();
+
+    for (name, instantiations) in instantiations {
+        if let Some(ty) = dropped_type(name) {
+            by_type
+                .entry(strip_crate_disambiguators(ty))
+                .or_insert_with(Default::default)
+                .merge(instantiations);
+        }
+    }
+
+    by_type
+}
+
+// legacy: core::ptr::drop_in_place<alloc::vec::Vec<u8>>
+// v0:     core[c1f1a4ba060b9bfa]::ptr::drop_in_place::<alloc[fdfd2bd8633a6659]::vec::Vec<u8>>
+fn dropped_type(name: &str) -> Option<&str> {
+    let (path, rest) = name.split_once("::drop_in_place")?;
+    if !path.ends_with("::ptr") {
+        return None;
+    }
+    let rest = rest.strip_prefix("::").unwrap_or(rest);
+    rest.strip_prefix('<')?.strip_suffix('>')
+}
+
+#[test]
+fn test_drop_glue_by_type() {
+    let mut instantiations = Map::new();
+    for (name, lines) in [
+        ("core::ptr::drop_in_place<alloc::vec::Vec<u8>>", 10),
+        (
+            "core[c1f1a4ba060b9bfa]::ptr::drop_in_place::<alloc[fdfd2bd8633a6659]::vec::Vec<u8>>",
+            12,
+        ),
+        ("core::ptr::drop_in_place<[alloc::string::String]>", 20),
+        ("demo::ptr::drop_in_place_all<u8>", 5),
+        ("alloc::vec::Vec<T,A>::push", 30),
+    ] {
+        let mut function = Instantiations::default();
+        function.record_lines(lines);
+        instantiations.insert(name.to_owned(), function);
+    }
+
+    let by_type = drop_glue_by_type(&instantiations);
+    assert_eq!(by_type.len(), 2);
+    let vec = &by_type["alloc::vec::Vec<u8>"];
+    assert_eq!((vec.copies, vec.total_lines), (2, 22));
+    assert_eq!(by_type["[alloc::string::String]"].total_lines, 20);
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeSet as Set, HashMap as Map};
+
+/// Functions that more than one crate, or more than one input file, has its
+/// own copy of.
+#[derive(Default)]
+pub struct Duplicates {
+    // Keyed by the instantiation, which unlike the demangled name includes
+    // the generic arguments.
+    functions: Map<String, Copies>,
+    // Functions defined by the current module. Whether they are #[inline] is
+    // known only once the attribute groups at the end of the module are seen,
+    // and their generic arguments once the debug info is.
+    pending: Vec<PendingFunction>,
+    current: Option<PendingFunction>,
+    inline_groups: Set<String>,
+    // !155 = distinct !DISubprogram(name: "push<u8, alloc::alloc::Global>", linkageName: "_ZN5alloc3vec16Vec$LT$T$C$A$GT$4push17ha22db2bd211d1904E", ...)
+    debug_names: Map<String, String>,
+    // Functions that could only be matched by their exact symbol.
+    unidentified: usize,
+}
+
+#[derive(Default)]
+struct Copies {
+    name: String,
+    crates: usize,
+    lines: usize,
+    largest: usize,
+    inline: bool,
+    exported: bool,
+}
+
+#[derive(Default)]
+struct PendingFunction {
+    symbol: String,
+    name: String,
+    lines: usize,
+    attribute_group: Option<String>,
+    debug_id: Option<String>,
+    exported: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Sharing {
+    Inline,
+    Shareable,
+    Exported,
+}
+
+impl Sharing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sharing::Inline => "inline",
+            Sharing::Shareable => "shareable",
+            Sharing::Exported => "exported",
+        }
+    }
+
+    pub fn explanation(self) -> &'static str {
+        match self {
+            Sharing::Inline => {
+                "#[inline] functions are instantiated by every crate that calls them, even with -Zshare-generics"
+            }
+            Sharing::Shareable => {
+                "with -Zshare-generics, a crate would reuse the copy of a crate it depends on"
+            }
+            Sharing::Exported => {
+                "a copy is already exported, but crates that do not depend on it made their own"
+            }
+        }
+    }
+}
+
+pub struct DuplicateReport {
+    pub name: String,
+    pub crates: usize,
+    pub duplicated_lines: usize,
+    pub sharing: Sharing,
+}
+
+impl Duplicates {
+    // define internal { ptr, i64 } @_ZN5alloc5alloc6Global18alloc_impl_runtime17hf5c29564433b6057E(i64 %layout.0, i64 %layout.1, i1 zeroext %zeroed) unnamed_addr #2 !dbg !1248 {
+    pub fn record_define(&mut self, line: &str) {
+        let linkage = line["define ".len()..].split_whitespace().next();
+        let exported = !matches!(linkage, Some("internal" | "private"));
+        let attributes = line.rfind(')').map_or("", |end| &line[end..]);
+        let attribute_group = attributes
+            .split_whitespace()
+            .find(|word| word.len() > 1 && word.starts_with('#'))
+            .map(str::to_owned);
+        let debug_id = attributes
+            .split_once("!dbg ")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .map(str::to_owned);
+        self.current = Some(PendingFunction {
+            attribute_group,
+            debug_id,
+            exported,
+            ..PendingFunction::default()
+        });
+    }
+
+    pub fn record_function(&mut self, symbol: &str, name: &str, lines: usize) {
+        let mut function = self.current.take().unwrap_or_default();
+        symbol.clone_into(&mut function.symbol);
+        name.clone_into(&mut function.name);
+        function.lines = lines;
+        self.pending.push(function);
+    }
+
+    pub fn record_metadata(&mut self, line: &str) {
+        let Some((id, rest)) = line.split_once(" = distinct !DISubprogram(name: \"") else {
+            return;
+        };
+        if let Some((name, _rest)) = rest.split_once('"') {
+            self.debug_names.insert(id.to_owned(), name.to_owned());
+        }
+    }
+
+    // attributes #2 = { inlinehint nonlazybind uwtable "probe-stack"="inline-asm" "target-cpu"="x86-64" }
+    pub fn record_attributes(&mut self, line: &str) {
+        let Some(rest) = line.strip_prefix("attributes ") else {
+            return;
+        };
+        let Some((group, attributes)) = rest.split_once(" = ") else {
+            return;
+        };
+        if attributes
+            .split(' ')
+            .any(|attribute| attribute == "inlinehint" || attribute == "alwaysinline")
+        {
+            self.inline_groups.insert(group.to_owned());
+        }
+    }
+
+    pub fn finish_module(&mut self) {
+        for function in self.pending.drain(..) {
+            let inline = function
+                .attribute_group
+                .is_some_and(|group| self.inline_groups.contains(&group));
+            let debug_name = function.debug_id.and_then(|id| self.debug_names.get(&id));
+            // Legacy symbols of generic functions have a hash that differs
+            // between the crates instantiating them, and a demangled name
+            // without the generic arguments. Those are in the debug info.
+            // V0 symbols demangle to a name that includes them.
+            let (key, name) = if function.symbol.starts_with("_R") {
+                (function.name.clone(), function.name)
+            } else if let Some(debug_name) = debug_name {
+                // push<u8, alloc::alloc::Global>
+                let generic_args = debug_name.find('<').map_or("", |i| &debug_name[i..]);
+                let name = function.name + generic_args;
+                (name.clone(), name)
+            } else {
+                self.unidentified += 1;
+                (function.symbol, function.name)
+            };
+            // Copies in several codegen units of the same crate count once.
+            let copies = self.functions.entry(key).or_insert(Copies {
+                name,
+                crates: 1,
+                lines: function.lines,
+                largest: function.lines,
+                ..Copies::default()
+            });
+            copies.inline |= inline;
+            copies.exported |= function.exported;
+        }
+        self.inline_groups.clear();
+        self.debug_names.clear();
+    }
+
+    /// Merges another codegen unit of the same crate, in which functions
+    /// that both define count as a single copy. Codegen units may be merged
+    /// in any order, so the larger of the two copies is kept.
+    pub fn merge_codegen_unit(&mut self, other: Duplicates) {
+        self.unidentified += other.unidentified;
+        for (key, other) in other.functions {
+            let copies = self.functions.entry(key).or_insert(Copies {
+                crates: 1,
+                ..Copies::default()
+            });
+            copies.name = other.name;
+            copies.lines = copies.lines.max(other.lines);
+            copies.largest = copies.largest.max(other.largest);
+            copies.inline |= other.inline;
+            copies.exported |= other.exported;
+        }
+    }
+
+    pub fn merge(&mut self, other: Duplicates) {
+        self.unidentified += other.unidentified;
+        for (key, other) in other.functions {
+            let copies = self.functions.entry(key).or_default();
+            copies.name = other.name;
+            copies.crates += other.crates;
+            copies.lines += other.lines;
+            copies.largest = copies.largest.max(other.largest);
+            copies.inline |= other.inline;
+            copies.exported |= other.exported;
+        }
+    }
+
+    /// The number of functions with neither a v0 symbol nor debug info,
+    /// whose copies in different crates cannot be matched if generic.
+    pub fn unidentified(&self) -> usize {
+        self.unidentified
+    }
+
+    pub fn report(&self) -> Vec<DuplicateReport> {
+        self.functions
+            .values()
+            .filter(|copies| copies.crates > 1)
+            .map(|copies| DuplicateReport {
+                name: copies.name.clone(),
+                crates: copies.crates,
+                duplicated_lines: copies.lines - copies.largest,
+                sharing: if copies.inline {
+                    Sharing::Inline
+                } else if copies.exported {
+                    Sharing::Exported
+                } else {
+                    Sharing::Shareable
+                },
+            })
+            .collect()
+    }
+}
+
+// Each function is a define line, the name in its debug info if any, and its
+// number of lines.
+#[cfg(test)]
+fn module(functions: &[(&str, &str, usize)]) -> Duplicates {
+    let mut duplicates = Duplicates::default();
+    for (i, &(define, debug_name, lines)) in functions.iter().enumerate() {
+        let symbol = define
+            .split_once("@\"")
+            .unwrap()
+            .1
+            .split('"')
+            .next()
+            .unwrap();
+        if debug_name.is_empty() {
+            duplicates.record_define(&format!("{} {{", define));
+        } else {
+            duplicates.record_define(&format!("{} !dbg !{} {{", define, i));
+        }
+        let name = crate::count::demangle_symbol(symbol);
+        duplicates.record_function(symbol, &name, lines);
+        if !debug_name.is_empty() {
+            duplicates.record_metadata(&format!(
+                "!{} = distinct !DISubprogram(name: \"{}\", scope: !0)",
+                i, debug_name,
+            ));
+        }
+    }
+    duplicates.record_attributes("attributes #0 = { inlinehint nonlazybind uwtable }");
+    duplicates.record_attributes("attributes #1 = { nonlazybind uwtable }");
+    duplicates.finish_module();
+    duplicates
+}
+
+#[cfg(test)]
+fn report(duplicates: &Duplicates) -> Vec<(String, usize, usize, &'static str)> {
+    let mut report: Vec<_> = duplicates
+        .report()
+        .into_iter()
+        .map(|x| (x.name, x.crates, x.duplicated_lines, x.sharing.name()))
+        .collect();
+    report.sort();
+    report
+}
+
+#[test]
+fn test_duplicate_instantiations() {
+    const PUSH_U8: &str = "push<u8, alloc::alloc::Global>";
+    const PUSH_U16: &str = "push<u16, alloc::alloc::Global>";
+    let push = |hash: &str| {
+        format!(
+            "define internal void @\"_ZN5alloc3vec16Vec$LT$T$C$A$GT$4push17h{}E\"(ptr %self, i8 %value) unnamed_addr #0",
+            hash,
+        )
+    };
+    let (a, b, c) = (
+        push("a22db2bd211d1904"),
+        push("4275f03f321534df"),
+        push("5ebb9687c6e6c6ee"),
+    );
+
+    // The same instantiation in two crates, whose legacy symbols differ.
+    let mut duplicates = module(&[(&a, PUSH_U8, 10)]);
+    duplicates.merge(module(&[(&b, PUSH_U8, 12)]));
+    let expected = "alloc::vec::Vec<T,A>::push<u8, alloc::alloc::Global>";
+    assert_eq!(
+        report(&duplicates),
+        [(expected.to_owned(), 2, 10, "inline")]
+    );
+
+    // Different instantiations with the same demangled name.
+    let mut duplicates = module(&[(&a, PUSH_U8, 10)]);
+    duplicates.merge(module(&[(&c, PUSH_U16, 10)]));
+    assert_eq!(report(&duplicates), []);
+
+    // Codegen units of one crate count once.
+    let mut krate = module(&[(&a, PUSH_U8, 10)]);
+    krate.merge_codegen_unit(module(&[(&a, PUSH_U8, 10)]));
+    let mut duplicates = Duplicates::default();
+    duplicates.merge(krate);
+    assert_eq!(report(&duplicates), []);
+    duplicates.merge(module(&[(&b, PUSH_U8, 10)]));
+    assert_eq!(
+        report(&duplicates),
+        [(expected.to_owned(), 2, 10, "inline")]
+    );
+
+    // Without debug info, only the exact symbol can be matched.
+    let mut duplicates = module(&[(&a, "", 10)]);
+    duplicates.merge(module(&[(&b, "", 10)]));
+    assert_eq!(report(&duplicates), []);
+    assert_eq!(duplicates.unidentified(), 2);
+}
+
+#[test]
+fn test_sharing() {
+    let inline = "define internal void @\"_ZN1c6inline17h0000000000000001E\"() unnamed_addr #0";
+    let shareable =
+        "define internal void @\"_ZN1c9shareable17h0000000000000002E\"() unnamed_addr #1";
+    let exported = "define void @\"_ZN1c8exported17h0000000000000003E\"() unnamed_addr #1";
+    let internal = "define internal void @\"_ZN1c8exported17h0000000000000003E\"() unnamed_addr #1";
+
+    let mut duplicates = module(&[
+        (inline, "inline", 5),
+        (shareable, "shareable", 5),
+        (exported, "exported", 5),
+    ]);
+    duplicates.merge(module(&[
+        (inline, "inline", 5),
+        (shareable, "shareable", 5),
+        (internal, "exported", 5),
+    ]));
+    assert_eq!(
+        report(&duplicates),
+        [
+            ("c::exported".to_owned(), 2, 5, "exported"),
+            ("c::inline".to_owned(), 2, 5, "inline"),
+            ("c::shareable".to_owned(), 2, 5, "shareable"),
+        ],
+    );
+}
@@ -9,7 +9,12 @@ pub enum Error {
     Msg(&'static str),
     Io(io::Error),
     PathIo(PathBuf, io::Error),
+    PathLine(PathBuf, usize, String),
     Quote(shlex::QuoteError),
+    Json(serde_json::Error),
+    MultipleTargets(String, Vec<String>),
+    Pattern(glob::PatternError),
+    NoMatch(String),
 }
 
 impl From<io::Error> for Error {
@@ -24,13 +29,45 @@ impl From<shlex::QuoteError> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
+impl From<glob::PatternError> for Error {
+    fn from(error: glob::PatternError) -> Self {
+        Error::Pattern(error)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Msg(msg) => formatter.write_str(msg),
             Error::Io(e) => Display::fmt(e, formatter),
             Error::PathIo(path, e) => write!(formatter, "{}: {}", path.display(), e),
+            Error::PathLine(path, line, msg) => {
+                write!(formatter, "{}:{}: {}", path.display(), line, msg)
+            }
             Error::Quote(e) => Display::fmt(e, formatter),
+            Error::Json(e) => Display::fmt(e, formatter),
+            Error::Pattern(e) => Display::fmt(e, formatter),
+            Error::NoMatch(pattern) => write!(formatter, "{}: no files matched", pattern),
+            Error::MultipleTargets(package, flags) => {
+                writeln!(
+                    formatter,
+                    "package `{}` has more than one target; select one of them with:",
+                    package,
+                )?;
+                for flag in flags {
+                    writeln!(formatter, "    {}", flag)?;
+                }
+                write!(
+                    formatter,
+                    "or analyze several at once with --bins, --tests, --examples, --benches, or --all-targets",
+                )
+            }
         }
     }
 }
@@ -38,10 +75,15 @@ impl Display for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Error::Msg(_) => None,
+            Error::Msg(_)
+            | Error::PathLine(..)
+            | Error::MultipleTargets(..)
+            | Error::NoMatch(_) => None,
             Error::Io(e) => e.source(),
             Error::PathIo(_path, e) => e.source(),
             Error::Quote(e) => e.source(),
+            Error::Json(e) => e.source(),
+            Error::Pattern(e) => e.source(),
         }
     }
 }
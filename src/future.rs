@@ -0,0 +1,207 @@
+use crate::count::{demangle_symbol, Instantiations};
+use crate::drop::drop_glue_by_type;
+use crate::name::strip_crate_disambiguators;
+use std::collections::HashMap as Map;
+
+/// The `poll` functions of the state machines generated for async fns and
+/// async blocks.
+#[derive(Default)]
+pub struct Futures {
+    poll_functions: Map<String, PollFunction>,
+    // Whether the poll function with the given mangled symbol belongs to an
+    // async fn or an async block, according to the debug info.
+    debug_kinds: Map<String, Kind>,
+    current: FunctionScan,
+}
+
+#[derive(Default)]
+struct PollFunction {
+    instantiations: Instantiations,
+    states: usize,
+    symbols: Vec<String>,
+}
+
+#[derive(Copy, Clone)]
+enum Kind {
+    AsyncFn,
+    AsyncBlock,
+}
+
+#[derive(Default)]
+struct FunctionScan {
+    is_async: bool,
+    in_first_switch: bool,
+    switch_cases: Option<usize>,
+}
+
+pub struct AsyncReport {
+    pub name: String,
+    pub copies: usize,
+    pub poll_lines: usize,
+    pub states: usize,
+    pub drop_glue_lines: usize,
+}
+
+impl Futures {
+    pub fn record_body_line(&mut self, line: &str) {
+        let scan = &mut self.current;
+        if scan.in_first_switch {
+            // switch i32 %_33, label %bb9 [
+            //   i32 0, label %bb1
+            //   i32 1, label %bb32
+            // ], !dbg !489
+            if line.starts_with("  ]") {
+                scan.in_first_switch = false;
+            } else if let Some(cases) = &mut scan.switch_cases {
+                *cases += 1;
+            }
+        } else if line.starts_with("  switch ") && scan.switch_cases.is_none() {
+            scan.in_first_switch = true;
+            scan.switch_cases = Some(0);
+        } else if (line.starts_with("  call ") || line.contains(" = call "))
+            && line.contains("panic_const_async")
+        {
+            // The state machine of every async fn and async block panics
+            // with panic_const_async_fn_resumed if polled after completion.
+            scan.is_async = true;
+        }
+    }
+
+    /// Forgets the body scanned so far, for a function that is not recorded.
+    pub fn discard_function(&mut self) {
+        self.current = FunctionScan::default();
+    }
+
+    pub fn record_function(&mut self, symbol: &str, lines: usize) {
+        let scan = std::mem::take(&mut self.current);
+        if !scan.is_async {
+            return;
+        }
+
+        let poll_function = self
+            .poll_functions
+            .entry(demangle_symbol(symbol))
+            .or_insert_with(Default::default);
+        poll_function.instantiations.record_lines(lines);
+        poll_function.states = poll_function.states.max(scan.switch_cases.unwrap_or(0));
+        poll_function.symbols.push(symbol.to_owned());
+    }
+
+    // !466 = distinct !DISubprogram(name: "{async_fn#0}", linkageName: "_ZN1b4work28_$u7b$$u7b$closure$u7d$$u7d$17h56306d01e971733fE", ...)
+    pub fn record_metadata(&mut self, line: &str) {
+        let Some((_id, rest)) = line.split_once(" = distinct !DISubprogram(name: \"{async_") else {
+            return;
+        };
+        let kind = if rest.starts_with("fn#") {
+            Kind::AsyncFn
+        } else if rest.starts_with("block#") {
+            Kind::AsyncBlock
+        } else {
+            return;
+        };
+        let Some((_name, rest)) = rest.split_once(", linkageName: \"") else {
+            return;
+        };
+        if let Some((symbol, _rest)) = rest.split_once('"') {
+            self.debug_kinds.insert(symbol.to_owned(), kind);
+        }
+    }
+
+    pub fn merge(&mut self, other: Futures) {
+        for (name, other) in other.poll_functions {
+            let poll_function = self
+                .poll_functions
+                .entry(name)
+                .or_insert_with(Default::default);
+            poll_function.instantiations.merge(&other.instantiations);
+            poll_function.states = poll_function.states.max(other.states);
+            poll_function.symbols.extend(other.symbols);
+        }
+        self.debug_kinds.extend(other.debug_kinds);
+    }
+
+    pub fn is_poll_function(&self, name: &str) -> bool {
+        self.poll_functions.contains_key(name)
+    }
+
+    pub fn report(&self, instantiations: &Map<String, Instantiations>) -> Vec<AsyncReport> {
+        let drop_glue = drop_glue_by_type(instantiations);
+
+        self.poll_functions
+            .iter()
+            .map(|(name, poll_function)| {
+                // The future's type has the same path as its poll function:
+                // core::ptr::drop_in_place<b::work::{{closure}}>
+                let drop_glue_lines = drop_glue
+                    .get(&strip_crate_disambiguators(name))
+                    .map_or(0, |drop_glue| drop_glue.total_lines);
+                let kind = poll_function
+                    .symbols
+                    .iter()
+                    .find_map(|symbol| self.debug_kinds.get(symbol));
+                let name = match kind {
+                    Some(Kind::AsyncFn) => {
+                        let enclosing = name.rsplit_once("::").map_or(name.as_str(), |x| x.0);
+                        format!("async fn {}", enclosing)
+                    }
+                    Some(Kind::AsyncBlock) => format!("async block {}", name),
+                    None => name.clone(),
+                };
+                AsyncReport {
+                    name,
+                    copies: poll_function.instantiations.copies,
+                    poll_lines: poll_function.instantiations.total_lines,
+                    states: poll_function.states,
+                    drop_glue_lines,
+                }
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_async_report() {
+    const WORK: &str = "_ZN1b4work28_$u7b$$u7b$closure$u7d$$u7d$17h56306d01e971733fE";
+    const HELPER: &str = "_ZN1b6helper17h0123456789abcdefE";
+
+    let mut futures = Futures::default();
+    for line in [
+        "  %_5 = load i32, ptr %self, align 4",
+        "  switch i32 %_5, label %bb9 [",
+        "    i32 0, label %bb1",
+        "    i32 1, label %bb8",
+        "    i32 3, label %bb7",
+        "  ], !dbg !489",
+        "  switch i32 %_7, label %bb2 [",
+        "    i32 0, label %bb3",
+        "  ]",
+        "  call void @_ZN4core9panicking11panic_const24panic_const_async_fn_resumed17h1b6bc2a04a1f1bd4E() #12",
+    ] {
+        futures.record_body_line(line);
+    }
+    futures.record_function(WORK, 40);
+    futures.record_body_line("  switch i32 %x, label %bb2 [");
+    futures.record_function(HELPER, 10);
+    futures.record_metadata(&format!(
+        "!466 = distinct !DISubprogram(name: \"{{async_fn#0}}\", linkageName: \"{}\", scope: !467)",
+        WORK,
+    ));
+
+    let mut instantiations = Map::new();
+    let mut drop_glue = Instantiations::default();
+    drop_glue.record_lines(6);
+    instantiations.insert(
+        "core::ptr::drop_in_place<b::work::{{closure}}>".to_owned(),
+        drop_glue,
+    );
+
+    assert!(futures.is_poll_function("b::work::{{closure}}"));
+    assert!(!futures.is_poll_function("b::helper"));
+    let report = futures.report(&instantiations);
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].name, "async fn b::work");
+    assert_eq!(report[0].copies, 1);
+    assert_eq!(report[0].poll_lines, 40);
+    assert_eq!(report[0].states, 3);
+    assert_eq!(report[0].drop_glue_lines, 6);
+}
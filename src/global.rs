@@ -0,0 +1,271 @@
+use crate::count::demangle_symbol;
+use std::collections::HashMap as Map;
+
+/// Static data defined by `@name = ... global/constant` lines, grouped by
+/// the symbol that owns it or, for compiler-generated anonymous allocations,
+/// by what kind of data it is.
+#[derive(Default)]
+pub struct Globals {
+    pub by_owner: Map<String, GlobalData>,
+    type_definitions: Map<String, String>,
+    pointer_size: Option<usize>,
+}
+
+#[derive(Default)]
+pub struct GlobalData {
+    pub count: usize,
+    pub bytes: usize,
+}
+
+impl Globals {
+    pub fn merge(&mut self, other: Globals) {
+        for (owner, data) in other.by_owner {
+            let merged = self.by_owner.entry(owner).or_insert_with(Default::default);
+            merged.count += data.count;
+            merged.bytes += data.bytes;
+        }
+    }
+
+    // %"core::fmt::Arguments<'_>" = type { { ptr, i64 }, { ptr, i64 }, ... }
+    pub fn record_type_definition(&mut self, line: &str) {
+        if let Some((name, body)) = line.split_once(" = type ") {
+            self.type_definitions
+                .insert(name.to_owned(), body.to_owned());
+        }
+    }
+
+    // target datalayout = "e-m:e-p270:32:32-p271:32:32-p272:64:64-i64:64-..."
+    pub fn record_datalayout(&mut self, line: &str) {
+        let Some(layout) = line.strip_prefix("target datalayout = ") else {
+            return;
+        };
+        for spec in layout.trim_matches('"').split('-') {
+            if let Some(rest) = spec.strip_prefix("p:") {
+                let bits = rest.split(':').next().and_then(|bits| bits.parse().ok());
+                self.pointer_size = bits.map(|bits: usize| bits / 8);
+            }
+        }
+    }
+
+    // @alloc_04367ef9775cf49df9760b8cd18fd0cd = private unnamed_addr constant [5 x i8] c"a.rs\00", align 1
+    pub fn record_global(&mut self, line: &str) {
+        let Some((name, rest)) = split_global_name(line) else {
+            return;
+        };
+        let Some(rest) = rest.strip_prefix(" = ") else {
+            return;
+        };
+
+        let mut rest = rest;
+        loop {
+            let (word, after) = rest.split_once(' ').unwrap_or((rest, ""));
+            match word {
+                "global" | "constant" => {
+                    rest = after;
+                    break;
+                }
+                // Declarations of data defined elsewhere, and aliases of
+                // other globals, do not occupy any space of their own.
+                "" | "external" | "extern_weak" | "alias" | "ifunc" => return,
+                _ => rest = after,
+            }
+        }
+
+        let layout = Layout {
+            type_definitions: &self.type_definitions,
+            pointer_size: self.pointer_size.unwrap_or(8),
+        };
+        let Some((size, _align, initializer)) = layout.parse_type(rest) else {
+            return;
+        };
+
+        let owner = owner(name, initializer);
+        let data = self.by_owner.entry(owner).or_insert_with(Default::default);
+        data.count += 1;
+        data.bytes += size;
+    }
+}
+
+fn split_global_name(line: &str) -> Option<(&str, &str)> {
+    let line = line.strip_prefix('@')?;
+    if let Some(quoted) = line.strip_prefix('"') {
+        let end = quoted.find('"')?;
+        Some((&quoted[..end], &quoted[end + 1..]))
+    } else {
+        let end = line.find(' ')?;
+        Some((&line[..end], &line[end..]))
+    }
+}
+
+fn owner(name: &str, initializer: &str) -> String {
+    let initializer = initializer.trim_start();
+    if name.starts_with("vtable.") {
+        "(vtables)".to_owned()
+    } else if name.starts_with("alloc_") {
+        // core::panic::Location { file: &str, line: u32, col: u32 }, as
+        // produced by #[track_caller] and panicking code.
+        if initializer.starts_with("<{ ptr @alloc_") && initializer.contains("[16 x i8] c\"") {
+            "(caller locations)".to_owned()
+        } else if initializer.starts_with("c\"") && is_text(initializer) {
+            "(string literals)".to_owned()
+        } else {
+            "(anonymous constants)".to_owned()
+        }
+    } else if name.starts_with("anon.") {
+        "(anonymous constants)".to_owned()
+    } else {
+        demangle_symbol(name)
+    }
+}
+
+// c"unsafe precondition(s) violated: ...\0A\0AThis indicates a bug...\00"
+fn is_text(initializer: &str) -> bool {
+    let Some(content) = initializer.strip_prefix("c\"") else {
+        return false;
+    };
+    let Some(end) = content.find('"') else {
+        return false;
+    };
+    let mut bytes = Vec::new();
+    let mut chars = content[..end].bytes();
+    while let Some(byte) = chars.next() {
+        if byte == b'\\' {
+            let hi = chars.next().and_then(hex_digit);
+            let lo = chars.next().and_then(hex_digit);
+            match (hi, lo) {
+                (Some(hi), Some(lo)) => bytes.push(hi << 4 | lo),
+                _ => return false,
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+    if bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    !bytes.is_empty()
+        && std::str::from_utf8(&bytes).is_ok_and(|text| {
+            text.chars()
+                .all(|ch| !ch.is_control() || ch == '\n' || ch == '\t' || ch == '\r')
+        })
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char)
+        .to_digit(16)
+        .and_then(|digit| u8::try_from(digit).ok())
+}
+
+struct Layout<'a> {
+    type_definitions: &'a Map<String, String>,
+    pointer_size: usize,
+}
+
+impl Layout<'_> {
+    /// Parses the LLVM type at the start of `ty`, returning its allocation
+    /// size and alignment in bytes and the remainder of the input.
+    fn parse_type<'t>(&self, ty: &'t str) -> Option<(usize, usize, &'t str)> {
+        let ty = ty.trim_start();
+        if let Some(rest) = ty.strip_prefix('[') {
+            let (len, rest) = rest.trim_start().split_once(" x ")?;
+            let len: usize = len.parse().ok()?;
+            let (size, align, rest) = self.parse_type(rest)?;
+            let rest = rest.trim_start().strip_prefix(']')?;
+            Some((len * size, align, rest))
+        } else if let Some(rest) = ty.strip_prefix("<{") {
+            let (size, _align, rest) = self.parse_fields(rest, "}>", true)?;
+            Some((size, 1, rest))
+        } else if let Some(rest) = ty.strip_prefix('{') {
+            self.parse_fields(rest, "}", false)
+        } else if let Some(rest) = ty.strip_prefix('<') {
+            let (len, rest) = rest.trim_start().split_once(" x ")?;
+            let len: usize = len.parse().ok()?;
+            let (size, _align, rest) = self.parse_type(rest)?;
+            let rest = rest.trim_start().strip_prefix('>')?;
+            let size = (len * size).next_power_of_two();
+            Some((size, size, rest))
+        } else if let Some(rest) = ty.strip_prefix('%') {
+            let end = if let Some(quoted) = rest.strip_prefix('"') {
+                quoted.find('"')? + 2
+            } else {
+                rest.find(|ch: char| !(ch.is_alphanumeric() || "._$-".contains(ch)))
+                    .unwrap_or(rest.len())
+            };
+            let name = &ty[..=end];
+            let body = self.type_definitions.get(name)?;
+            if body == "opaque" {
+                return Some((0, 1, &rest[end..]));
+            }
+            let (size, align, _) = self.parse_type(body)?;
+            Some((size, align, &rest[end..]))
+        } else if let Some(rest) = ty.strip_prefix("ptr") {
+            let rest = match rest.strip_prefix(" addrspace(") {
+                Some(rest) => &rest[rest.find(')')? + 1..],
+                None => rest,
+            };
+            Some((self.pointer_size, self.pointer_size, rest))
+        } else if let Some(rest) = ty.strip_prefix('i') {
+            let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+            let bits: usize = rest[..digits].parse().ok()?;
+            let size = bits.div_ceil(8).next_power_of_two();
+            Some((size, size.min(16), &rest[digits..]))
+        } else {
+            let primitives = [
+                ("half", 2),
+                ("bfloat", 2),
+                ("float", 4),
+                ("double", 8),
+                ("x86_fp80", 16),
+                ("fp128", 16),
+                ("ppc_fp128", 16),
+            ];
+            for (name, size) in primitives {
+                if let Some(rest) = ty.strip_prefix(name) {
+                    return Some((size, size, rest));
+                }
+            }
+            None
+        }
+    }
+
+    fn parse_fields<'t>(
+        &self,
+        mut rest: &'t str,
+        close: &str,
+        packed: bool,
+    ) -> Option<(usize, usize, &'t str)> {
+        let mut size = 0usize;
+        let mut struct_align = 1;
+        loop {
+            rest = rest.trim_start();
+            if let Some(rest) = rest.strip_prefix(close) {
+                let size = size.next_multiple_of(struct_align);
+                return Some((size, struct_align, rest));
+            }
+            let (field_size, field_align, after) = self.parse_type(rest)?;
+            if !packed {
+                size = size.next_multiple_of(field_align);
+                struct_align = struct_align.max(field_align);
+            }
+            size += field_size;
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest);
+        }
+    }
+}
+
+#[test]
+fn test_type_size() {
+    let mut type_definitions = Map::new();
+    type_definitions.insert("%Pair".to_owned(), "{ i8, i64 }".to_owned());
+    let layout = Layout {
+        type_definitions: &type_definitions,
+        pointer_size: 8,
+    };
+    let size = |ty| layout.parse_type(ty).map(|(size, _align, _rest)| size);
+    assert_eq!(size("[5 x i8] c\"a.rs\\00\""), Some(5));
+    assert_eq!(size("<{ ptr, [16 x i8] }> <{ ... }>"), Some(24));
+    assert_eq!(size("{ i8, i32, i8 }"), Some(12));
+    assert_eq!(size("[2 x %Pair] zeroinitializer"), Some(32));
+    assert_eq!(size("<4 x float>"), Some(16));
+}
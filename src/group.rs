@@ -0,0 +1,97 @@
+use crate::count::Instantiations;
+use crate::name::{split_trait_impl, strip_crate_disambiguators};
+use crate::opts::GroupBy;
+use std::collections::HashMap as Map;
+
+/// Totals the functions that are methods of `<SelfType as Trait>` impls by
+/// either the trait or the impl. Everything else, such as inherent methods
+/// and free functions, is totaled in a single row.
+pub fn group_by(
+    instantiations: Map<String, Instantiations>,
+    group_by: GroupBy,
+) -> Map<String, Instantiations> {
+    let mut groups = Map::<String, Instantiations>::new();
+
+    for (name, instantiations) in instantiations {
+        let key = match (group_by, split_trait_impl(&name)) {
+            (GroupBy::Function, _) => name,
+            (GroupBy::Trait, Some((_self_ty, trait_path, _method))) => {
+                strip_crate_disambiguators(strip_generic_args(trait_path))
+            }
+            (GroupBy::Impl, Some((self_ty, trait_path, _method))) => {
+                strip_crate_disambiguators(&format!("<{} as {}>", self_ty, trait_path))
+            }
+            (GroupBy::Trait | GroupBy::Impl, None) => "(not a trait method)".to_owned(),
+        };
+        groups
+            .entry(key)
+            .or_insert_with(Default::default)
+            .merge(&instantiations);
+    }
+
+    groups
+}
+
+// core::ops::function::FnOnce<(u32,)> -> core::ops::function::FnOnce
+fn strip_generic_args(path: &str) -> &str {
+    if !path.ends_with('>') {
+        return path;
+    }
+    let mut depth = 0;
+    for (i, ch) in path.char_indices().rev() {
+        match ch {
+            '>' => depth += 1,
+            '<' => {
+                depth -= 1;
+                if depth == 0 {
+                    return if i == 0 || path[..i].ends_with("::") {
+                        path
+                    } else {
+                        &path[..i]
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+    path
+}
+
+#[test]
+fn test_group_by() {
+    let mut instantiations = Map::new();
+    for (name, lines) in [
+        ("<a::Square as core::fmt::Debug>::fmt", 10),
+        ("<a::Circle as core::fmt::Debug>::fmt", 20),
+        ("<a::Circle as core::clone::Clone>::clone", 5),
+        (
+            "<a[1b6bc2a04a1f1bd4]::Job as core[c1f1a4ba060b9bfa]::ops::function::FnOnce<(u32,)>>::call_once",
+            7,
+        ),
+        ("a::Square::new", 3),
+    ] {
+        let mut function = Instantiations::default();
+        function.record_lines(lines);
+        instantiations.insert(name.to_owned(), function);
+    }
+
+    let lines = |groups: &Map<String, Instantiations>, key: &str| groups[key].total_lines;
+
+    let traits = group_by(instantiations.clone(), GroupBy::Trait);
+    assert_eq!(traits.len(), 4);
+    assert_eq!(lines(&traits, "core::fmt::Debug"), 30);
+    assert_eq!(lines(&traits, "core::clone::Clone"), 5);
+    assert_eq!(lines(&traits, "core::ops::function::FnOnce"), 7);
+    assert_eq!(lines(&traits, "(not a trait method)"), 3);
+
+    let impls = group_by(instantiations.clone(), GroupBy::Impl);
+    assert_eq!(impls.len(), 5);
+    assert_eq!(lines(&impls, "<a::Circle as core::fmt::Debug>"), 20);
+    assert_eq!(
+        lines(&impls, "<a::Job as core::ops::function::FnOnce<(u32,)>>"),
+        7,
+    );
+
+    let functions = group_by(instantiations, GroupBy::Function);
+    assert_eq!(functions.len(), 5);
+}
@@ -0,0 +1,308 @@
+use crate::bitcode;
+use crate::count::{count_lines, Analysis, Diagnostics};
+use crate::error::{Error, Result};
+use crate::opts::LlvmLines;
+use crate::zstd::ZstdDecoder;
+use flate2::read::MultiGzDecoder;
+use glob::{MatchOptions, Pattern};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Expands the arguments of --files into the list of files to analyze.
+/// Directories are searched recursively for files matching --include, and
+/// arguments that do not exist but contain glob metacharacters are expanded
+/// as globs.
+pub fn expand_paths(opts: &LlvmLines) -> Result<Vec<PathBuf>> {
+    let default_include =
+        ["*.ll", "*.ll.gz", "*.ll.zst", "*.bc"].map(|glob| Pattern::new(glob).unwrap());
+    let include = if opts.include.is_empty() {
+        &default_include[..]
+    } else {
+        &opts.include[..]
+    };
+    // --emit=llvm-ir,llvm-bc writes both forms of the same module. Unless
+    // asked for by --include, bitcode is only read where there is no IR.
+    let walk = Walk {
+        include,
+        prefer_text: opts.include.is_empty(),
+    };
+
+    let mut paths = Vec::new();
+    for arg in &opts.files {
+        if arg.is_dir() {
+            walk_dir(arg, &walk, &mut paths)?;
+        } else if !arg.exists() && is_glob(arg) {
+            let pattern = arg.to_string_lossy();
+            let mut matched = Vec::new();
+            for entry in glob::glob(&pattern)? {
+                let path = entry.map_err(|err| {
+                    let path = err.path().to_owned();
+                    Error::PathIo(path, err.into())
+                })?;
+                if path.is_dir() {
+                    walk_dir(&path, &walk, &mut matched)?;
+                } else {
+                    matched.push(path);
+                }
+            }
+            if matched.is_empty() {
+                return Err(Error::NoMatch(pattern.into_owned()));
+            }
+            matched.sort();
+            paths.extend(matched);
+        } else {
+            // Including `-` for standard input.
+            paths.push(arg.clone());
+        }
+    }
+
+    paths.retain(|path| {
+        !opts
+            .exclude_files
+            .iter()
+            .any(|pattern| matches(pattern, path))
+    });
+    Ok(paths)
+}
+
+/// Analyzes the input files on up to `jobs` threads. The analysis of each
+/// file is passed to `merge`, together with the index of its path, as soon as
+/// it is done, so that the analyses of all files are never held at once.
+pub fn count_inputs(
+    paths: &[PathBuf],
+    jobs: Option<usize>,
+    merge: impl FnMut(usize, Analysis) + Send,
+) -> Result<()> {
+    let jobs = jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+        .clamp(1, paths.len().max(1));
+    let next = AtomicUsize::new(0);
+    let merge = Mutex::new(merge);
+    let error = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(i) else {
+                    break;
+                };
+                let mut analysis = Analysis::default();
+                match count_input(&mut analysis, path) {
+                    Ok(()) => (*merge.lock().unwrap())(i, analysis),
+                    Err(err) => {
+                        error.lock().unwrap().get_or_insert(err);
+                        // Leave the remaining files to no thread.
+                        next.store(paths.len(), Ordering::Relaxed);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    match error.into_inner().unwrap() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Analyzes one input file, where `-` means standard input. Gzip and zstd
+/// compressed input is decompressed, and LLVM bitcode is recognized.
+pub fn count_input(analysis: &mut Analysis, path: &Path) -> Result<()> {
+    count(analysis, path).map_err(|err| Error::PathIo(path.to_owned(), err))
+}
+
+fn count(analysis: &mut Analysis, path: &Path) -> io::Result<()> {
+    let mut reader = BufReader::new(open_input(path)?);
+    if bitcode::is_bitcode(reader.fill_buf()?) {
+        // Bitcode is not line oriented, and is several times smaller than
+        // the equivalent text.
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        bitcode::count_bitcode(analysis, &data)
+    } else {
+        let diagnostics = count_lines(analysis, reader)?;
+        print_diagnostics(path, &diagnostics);
+        Ok(())
+    }
+}
+
+// At most this many warnings are printed per file, followed by the number of
+// functions that were skipped in total.
+const MAX_WARNINGS: usize = 10;
+
+fn print_diagnostics(path: &Path, diagnostics: &Diagnostics) {
+    if diagnostics.warnings.is_empty() {
+        return;
+    }
+
+    let mut stderr = io::stderr().lock();
+    for (line, message) in diagnostics.warnings.iter().take(MAX_WARNINGS) {
+        let _ = writeln!(stderr, "warning: {}:{}: {}", path.display(), line, message);
+    }
+    if diagnostics.warnings.len() > MAX_WARNINGS {
+        let _ = writeln!(
+            stderr,
+            "warning: {}: {} more warnings not shown",
+            path.display(),
+            diagnostics.warnings.len() - MAX_WARNINGS,
+        );
+    }
+    if diagnostics.skipped_functions > 0 {
+        let _ = writeln!(
+            stderr,
+            "warning: {}: {} function{} not counted",
+            path.display(),
+            diagnostics.skipped_functions,
+            if diagnostics.skipped_functions == 1 {
+                " was"
+            } else {
+                "s were"
+            },
+        );
+    }
+}
+
+fn open_input(path: &Path) -> io::Result<Box<dyn Read>> {
+    let reader: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(path)?)
+    };
+
+    // Recognize compressed input by its magic number rather than by the
+    // extension, so that compressed standard input works too.
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok(Box::new(ZstdDecoder::new(reader)?))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+struct Walk<'a> {
+    include: &'a [Pattern],
+    prefer_text: bool,
+}
+
+fn walk_dir(dir: &Path, walk: &Walk, paths: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = Vec::new();
+    let read_dir = fs::read_dir(dir).map_err(|err| Error::PathIo(dir.to_owned(), err))?;
+    for entry in read_dir {
+        let entry = entry.map_err(|err| Error::PathIo(dir.to_owned(), err))?;
+        // Symlinked directories, like build/host in an x.py build directory,
+        // are not followed. They would count the files they lead to twice,
+        // or forever if they form a loop.
+        let file_type = entry
+            .file_type()
+            .map_err(|err| Error::PathIo(entry.path(), err))?;
+        entries.push((entry.path(), file_type.is_dir()));
+    }
+    entries.sort();
+
+    for (path, is_dir) in &entries {
+        if *is_dir {
+            walk_dir(path, walk, paths)?;
+        } else if walk.include.iter().any(|pattern| matches(pattern, path))
+            && !(walk.prefer_text && has_text_ir(path, &entries))
+        {
+            paths.push(path.clone());
+        }
+    }
+    Ok(())
+}
+
+// Whether `path` is bitcode next to which the same module was written as IR.
+fn has_text_ir(path: &Path, entries: &[(PathBuf, bool)]) -> bool {
+    if path.extension().is_none_or(|ext| ext != "bc") {
+        return false;
+    }
+    ["ll", "ll.gz", "ll.zst"].iter().any(|ext| {
+        let text = path.with_extension(ext);
+        entries.iter().any(|(entry, _is_dir)| *entry == text)
+    })
+}
+
+fn is_glob(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+// Patterns without a path separator, like `*.ll`, match the file name
+// anywhere in the tree. Others match the whole path.
+fn matches(pattern: &Pattern, path: &Path) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    if pattern.as_str().contains('/') {
+        pattern.matches_path_with(path, options)
+    } else {
+        path.file_name()
+            .is_some_and(|name| pattern.matches_with(&name.to_string_lossy(), options))
+    }
+}
+
+#[test]
+fn test_matches() {
+    let ll = Pattern::new("*.ll").unwrap();
+    assert!(matches(&ll, Path::new("build/x86_64/stage0/a.ll")));
+    assert!(!matches(&ll, Path::new("build/a.ll.gz")));
+    let stage1 = Pattern::new("build/*/stage1/*").unwrap();
+    assert!(matches(&stage1, Path::new("build/x86_64/stage1/a.ll")));
+    assert!(!matches(&stage1, Path::new("build/x86_64/stage0/a.ll")));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_walk_dir_symlinks() {
+    use std::os::unix::fs::symlink;
+
+    let dir = tempfile::tempdir().unwrap();
+    let target = dir.path().join("x86_64-unknown-linux-gnu");
+    fs::create_dir(&target).unwrap();
+    fs::write(target.join("a.ll"), "").unwrap();
+    symlink(&target, dir.path().join("host")).unwrap();
+    symlink(dir.path(), target.join("loop")).unwrap();
+
+    let mut paths = Vec::new();
+    let walk = Walk {
+        include: &[Pattern::new("*.ll").unwrap()],
+        prefer_text: true,
+    };
+    walk_dir(dir.path(), &walk, &mut paths).unwrap();
+    assert_eq!(paths, [target.join("a.ll")]);
+}
+
+#[test]
+fn test_walk_dir_bitcode() {
+    let dir = tempfile::tempdir().unwrap();
+    for file in ["a.ll", "a.bc", "b.bc"] {
+        fs::write(dir.path().join(file), "").unwrap();
+    }
+    let include = ["*.ll", "*.bc"].map(|glob| Pattern::new(glob).unwrap());
+
+    let mut paths = Vec::new();
+    let walk = Walk {
+        include: &include,
+        prefer_text: true,
+    };
+    walk_dir(dir.path(), &walk, &mut paths).unwrap();
+    assert_eq!(paths, [dir.path().join("a.ll"), dir.path().join("b.bc")]);
+
+    let mut paths = Vec::new();
+    let walk = Walk {
+        include: &include,
+        prefer_text: false,
+    };
+    walk_dir(dir.path(), &walk, &mut paths).unwrap();
+    assert_eq!(paths.len(), 3);
+}
@@ -0,0 +1,164 @@
+/// The result of scanning the text of a `define` so far.
+#[derive(PartialEq, Debug)]
+pub enum Header<'a> {
+    /// The `{` that opens the function body has not been seen yet, so the
+    /// header continues on the next line.
+    Incomplete,
+    /// The header is complete. The symbol is `None` if the header has no
+    /// function name.
+    Complete {
+        symbol: Option<String>,
+        rest: &'a str,
+    },
+}
+
+// define internal { ptr, i64 } @"_ZN4core3fmt9Arguments6as_str17h0d0e7bd8f3da2c2aE"({ ptr, i64 } %x) unnamed_addr #0 {
+//
+// Braces may appear in the return and parameter types, and quoted names may
+// contain any character, so the body begins at the first `{` that follows
+// the parameter list outside of any brackets.
+pub fn scan_define(header: &str) -> Header<'_> {
+    let mut symbol = None;
+    let mut params_done = false;
+    let mut depth = 0usize;
+    let mut chars = header.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => skip_quoted(&mut chars),
+            ';' => skip_comment(&mut chars),
+            '@' if symbol.is_none() && depth == 0 => {
+                let (name, len) = parse_name(&header[i + 1..]);
+                symbol = Some(name);
+                for _ in 0..len {
+                    chars.next();
+                }
+            }
+            '(' | '[' | '{' if !(params_done && depth == 0 && ch == '{') => depth += 1,
+            '{' => {
+                return Header::Complete {
+                    symbol: symbol.flatten(),
+                    rest: &header[i + 1..],
+                }
+            }
+            ')' | ']' | '}' => {
+                depth = depth.saturating_sub(1);
+                if ch == ')' && depth == 0 && symbol.is_some() {
+                    params_done = true;
+                }
+            }
+            _ => {}
+        }
+    }
+    Header::Incomplete
+}
+
+/// The change in `{` `}` nesting over a line of a function body, ignoring
+/// braces within quoted strings, such as inline assembly, and comments.
+pub fn brace_delta(line: &str) -> isize {
+    let mut delta = 0;
+    let mut chars = line.char_indices();
+    while let Some((_i, ch)) = chars.next() {
+        match ch {
+            '"' => skip_quoted(&mut chars),
+            ';' => skip_comment(&mut chars),
+            '{' => delta += 1,
+            '}' => delta -= 1,
+            _ => {}
+        }
+    }
+    delta
+}
+
+// LLVM escapes `"` and `\` inside of quoted strings as \22 and \5C, so the
+// string ends at the next quote.
+fn skip_quoted(chars: &mut std::str::CharIndices) {
+    for (_i, ch) in chars.by_ref() {
+        if ch == '"' {
+            break;
+        }
+    }
+}
+
+// A comment extends to the end of the line, which in a multi-line header
+// is not the end of the header.
+fn skip_comment(chars: &mut std::str::CharIndices) {
+    for (_i, ch) in chars.by_ref() {
+        if ch == '\n' {
+            break;
+        }
+    }
+}
+
+// Parses the name that follows an `@`: either quoted with escapes, as in
+// @"_ZN4core3ptr85drop_in_place$LT$std..rt..lang_start$LT$$LP$$RP$$GT$..$u7b$$u7b$closure$u7d$$u7d$$GT$17h6f0a1ef7b5e0b4e1E",
+// or a bare identifier or number, as in @main or @0. Returns the name and
+// the number of characters it occupies.
+fn parse_name(s: &str) -> (Option<String>, usize) {
+    if let Some(quoted) = s.strip_prefix('"') {
+        let Some(end) = quoted.find('"') else {
+            return (None, s.chars().count());
+        };
+        let name = unescape(&quoted[..end]);
+        return (Some(name), quoted[..end].chars().count() + 2);
+    }
+
+    let len = s
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || "-$._".contains(ch)))
+        .unwrap_or(s.len());
+    if len == 0 {
+        (None, 0)
+    } else {
+        (Some(s[..len].to_owned()), len)
+    }
+}
+
+// foo\22bar -> foo"bar
+fn unescape(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let (b'\\', Some(unescaped)) = (byte, hex) {
+            bytes.push(unescaped);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[test]
+fn test_scan_define() {
+    let complete = |symbol: &str| Header::Complete {
+        symbol: Some(symbol.to_owned()),
+        rest: "",
+    };
+    assert_eq!(
+        scan_define("define internal { ptr, i64 } @f({ i32, i32 } %x) unnamed_addr #0 {"),
+        complete("f"),
+    );
+    assert_eq!(
+        scan_define("define void @\"a(b)\\22c{\"(ptr %x) {"),
+        complete("a(b)\"c{"),
+    );
+    assert_eq!(scan_define("define void @0() {"), complete("0"));
+    assert_eq!(
+        scan_define("define void @f(ptr %x, ; {\n"),
+        Header::Incomplete
+    );
+    assert_eq!(
+        scan_define("define void @f(ptr %x, ; {\n  i64 %y)\n  #0 {"),
+        complete("f"),
+    );
+    assert_eq!(brace_delta("  call void asm \"{\", \"\"() ; }"), 0);
+    assert_eq!(
+        brace_delta("  %x = insertvalue { ptr, i64 } poison, ptr %p, 0"),
+        0
+    );
+    assert_eq!(brace_delta("}"), -1);
+}
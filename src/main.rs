@@ -16,23 +16,47 @@
     clippy::unwrap_or_default
 )]
 
+mod bitcode;
+mod bucket;
+mod category;
+mod closure;
 mod cmd;
 mod count;
+mod deps;
+mod drop;
+mod duplicate;
 mod error;
+mod future;
+mod global;
+mod group;
+mod input;
+mod lex;
+mod metadata;
+mod name;
 mod opts;
+mod ownership;
+mod select;
 mod table;
+mod vtable;
+mod zstd;
 
+use crate::closure::fold_closures;
 use crate::cmd::CommandExt as _;
-use crate::count::{count_lines, Instantiations};
+use crate::count::{Analysis, Instantiations};
+use crate::duplicate::Duplicates;
 use crate::error::{Error, Result};
-use crate::opts::{Coloring, LlvmLines, SortOrder, Subcommand};
-use clap::{CommandFactory, Parser};
-use regex::Regex;
+use crate::group::group_by;
+use crate::metadata::cargo_metadata;
+use crate::opts::{Coloring, GroupBy, LlvmLines, Report, Subcommand};
+use crate::ownership::Ownership;
+use crate::select::{Harness, Selection, TargetKind};
+use clap::{CommandFactory, Parser, ValueEnum as _};
 use std::collections::HashMap as Map;
 use std::env;
 use std::ffi::OsString;
 use std::fs;
 use std::io::{self, BufRead, IsTerminal, Write};
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::process::{self, Command, Stdio};
 use tempfile::TempDir;
@@ -43,7 +67,11 @@ cargo_subcommand_metadata::description!(
 );
 
 fn main() {
-    let Subcommand::LlvmLines(opts) = Subcommand::parse();
+    if let Some(deps_dir) = env::var_os(deps::DEPS_DIR) {
+        exit(deps::wrap_rustc(Path::new(&deps_dir)));
+    }
+
+    let Subcommand::LlvmLines(mut opts) = Subcommand::parse();
 
     if opts.help {
         let _ = Subcommand::command()
@@ -60,12 +88,18 @@ fn main() {
         return;
     }
 
-    let result = if opts.files.is_empty() {
-        cargo_llvm_lines(&opts)
-    } else {
-        read_llvm_ir_from_paths(&opts.files, opts.sort, opts.filter.as_ref())
-    };
+    let result = read_bucket_file(&mut opts).and_then(|()| {
+        if opts.files.is_empty() {
+            cargo_llvm_lines(&opts)
+        } else {
+            input::expand_paths(&opts).and_then(|paths| read_llvm_ir_from_paths(&paths, &opts))
+        }
+    });
+
+    exit(result);
+}
 
+fn exit(result: Result<i32>) -> ! {
     process::exit(match result {
         Ok(code) => code,
         Err(err) => {
@@ -75,76 +109,386 @@ fn main() {
     });
 }
 
+fn read_bucket_file(opts: &mut LlvmLines) -> Result<()> {
+    if let Some(path) = &opts.bucket_file {
+        let buckets = bucket::read_bucket_file(path)?;
+        opts.bucket.extend(buckets);
+    }
+    Ok(())
+}
+
 fn cargo_llvm_lines(opts: &LlvmLines) -> Result<i32> {
+    let selections = select::selections(opts)?;
+    let ownership = ownership(opts)?;
+    let ownership = ownership.as_ref();
+    if opts.deps {
+        return cargo_llvm_lines_deps(opts, &selections, ownership);
+    }
+
+    if selections.len() <= 1 {
+        let mut analysis = Analysis::default();
+        let exit = run_cargo_rustc(opts, selections.first(), &mut analysis)?;
+        if exit != 0 {
+            return Ok(exit);
+        }
+        print(&analysis, opts, ownership);
+        return Ok(0);
+    }
+
+    let mut results = Vec::new();
+    for selection in &selections {
+        let mut analysis = Analysis::default();
+        let exit = run_cargo_rustc(opts, Some(selection), &mut analysis)?;
+        if exit != 0 {
+            return Ok(exit);
+        }
+        results.push((selection.label.clone(), analysis));
+    }
+
+    let per_target = select::enumerates_targets(opts);
+    let summary = results
+        .iter()
+        .map(|(label, analysis)| (label.clone(), analysis.total()))
+        .collect();
+    table::print_selections(summary, opts.sort, per_target);
+
+    // Each target is shown separately followed by all of them combined.
+    // Packages are combined unless --per-package.
+    let sections = per_target || opts.per_package;
+    let combined = !opts.per_package;
+    let heading = if per_target { "Target" } else { "Package" };
+    let mut merged = Analysis::default();
+    for (label, analysis) in results {
+        if sections {
+            let _ = writeln!(io::stdout(), "{} {}:\n", heading, label);
+            print(&analysis, opts, ownership);
+            let _ = writeln!(io::stdout());
+        }
+        if combined {
+            merged.merge(analysis);
+        }
+    }
+    if combined {
+        if sections {
+            let _ = writeln!(io::stdout(), "Combined:\n");
+        }
+        print(&merged, opts, ownership);
+    }
+
+    Ok(0)
+}
+
+fn run_cargo_rustc(
+    opts: &LlvmLines,
+    selection: Option<&Selection>,
+    analysis: &mut Analysis,
+) -> Result<i32> {
     let outdir = tempfile::Builder::new()
         .prefix("cargo-llvm-lines")
         .tempdir()
         .expect("failed to create tmp file");
-    let outfile = outdir.path().join("crate");
 
+    let exit = run_cargo(opts, selection, &outdir)?;
+    if exit != 0 {
+        return Ok(exit);
+    }
+
+    for path in find_llvm_ir_in_dir(&outdir)? {
+        input::count_input(analysis, &path)?;
+    }
+    Ok(0)
+}
+
+fn cargo_llvm_lines_deps(
+    opts: &LlvmLines,
+    selections: &[Selection],
+    ownership: Option<&Ownership>,
+) -> Result<i32> {
+    if selections.len() > 1 {
+        return Err(Error::Msg(
+            "--deps requires selecting a single package and target",
+        ));
+    }
+
+    let outdir = tempfile::Builder::new()
+        .prefix("cargo-llvm-lines")
+        .tempdir()
+        .expect("failed to create tmp file");
+    let deps_dir = outdir.path().join("deps");
+    fs::create_dir(&deps_dir)?;
+
+    let exit = run_cargo(opts, selections.first(), &outdir)?;
+    if exit != 0 {
+        return Ok(exit);
+    }
+
+    let crates = deps::read_deps_dir(&deps_dir)?;
+    let summary = crates
+        .iter()
+        .map(|(crate_name, analysis)| (crate_name.clone(), analysis.total()))
+        .collect();
+    table::print_crates(summary, opts.sort);
+
+    let mut merged = Analysis::default();
+    for analysis in crates.into_values() {
+        merged.merge(analysis);
+    }
+    print(&merged, opts, ownership);
+
+    Ok(0)
+}
+
+fn run_cargo(opts: &LlvmLines, selection: Option<&Selection>, outdir: &TempDir) -> Result<i32> {
     // If cargo-llvm-lines was invoked from cargo, use the cargo that invoked it.
     let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
     let mut cmd = Command::new(cargo);
-    propagate_opts(&mut cmd, opts, &outfile);
+    propagate_opts(&mut cmd, opts, selection, outdir.path());
     cmd.env("CARGO_INCREMENTAL", "");
     cmd.stdout(Stdio::inherit());
 
+    if opts.deps {
+        // Cargo runs this same executable as rustc, which emits IR for every
+        // crate into the deps directory.
+        cmd.env(deps::DEPS_DIR, outdir.path().join("deps"));
+        cmd.env("RUSTC", env::current_exe()?);
+        let rustc = env::var_os("RUSTC").or_else(|| env::var_os("CARGO_BUILD_RUSTC"));
+        if let Some(rustc) = rustc {
+            cmd.env(deps::RUSTC, rustc);
+        }
+    }
+
     if opts.verbose {
         let color = opts.color.unwrap_or(Coloring::Auto);
         print_command(&cmd, color)?;
     }
 
-    let exit = filter_err(&mut cmd)?;
-    if exit != 0 {
-        return Ok(exit);
-    }
-
-    let ir = read_llvm_ir_from_dir(&outdir)?;
-    let mut instantiations = Map::<String, Instantiations>::new();
-    count_lines(&mut instantiations, &ir);
-    table::print(instantiations, opts.sort, opts.filter.as_ref());
-
-    Ok(0)
+    filter_err(&mut cmd)
 }
 
-fn read_llvm_ir_from_dir(outdir: &TempDir) -> Result<Vec<u8>> {
+// Rustc may write more than one file, for example one per codegen unit or
+// per crate type. All of them are analyzed together.
+fn find_llvm_ir_in_dir(outdir: &TempDir) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
     for file in fs::read_dir(outdir)? {
         let path = file?.path();
         if let Some(ext) = path.extension() {
             if ext == "ll" {
-                let content = fs::read(path)?;
-                return Ok(content);
+                paths.push(path);
             }
         }
     }
 
-    Err(Error::Msg("Ran --emit=llvm-ir but did not find output IR"))
+    if paths.is_empty() {
+        return Err(Error::Msg("Ran --emit=llvm-ir but did not find output IR"));
+    }
+    paths.sort();
+    Ok(paths)
 }
 
-fn read_llvm_ir_from_paths(
-    paths: &[PathBuf],
-    sort_order: SortOrder,
-    function_filter: Option<&Regex>,
-) -> Result<i32> {
-    let mut instantiations = Map::<String, Instantiations>::new();
-
-    for path in paths {
-        match fs::read(path) {
-            Ok(ir) => count_lines(&mut instantiations, &ir),
-            Err(err) => return Err(Error::PathIo(path.clone(), err)),
+fn read_llvm_ir_from_paths(paths: &[PathBuf], opts: &LlvmLines) -> Result<i32> {
+    let ownership = ownership(opts)?;
+    let mut analysis = Analysis::default();
+    let mut crates = Vec::<CrateFiles>::new();
+    input::count_inputs(paths, opts.jobs, |i, mut file| {
+        let label = crate_name_of_file(&paths[i]);
+        let k = crates
+            .iter()
+            .position(|krate| krate.label == label)
+            .unwrap_or_else(|| {
+                crates.push(CrateFiles {
+                    first: i,
+                    label,
+                    duplicates: Duplicates::default(),
+                    instantiations: Map::new(),
+                });
+                crates.len() - 1
+            });
+        let krate = &mut crates[k];
+        krate.first = krate.first.min(i);
+        krate
+            .duplicates
+            .merge_codegen_unit(mem::take(&mut file.duplicates));
+        if opts.per_file {
+            for (name, instantiations) in &file.instantiations {
+                krate
+                    .instantiations
+                    .entry(name.clone())
+                    .or_insert_with(Default::default)
+                    .merge(instantiations);
+            }
+        }
+        analysis.merge(file);
+    })?;
+
+    // Files finish in any order, but crates are listed in the order of
+    // their first file.
+    crates.sort_by_key(|krate| krate.first);
+    let mut per_file = Vec::new();
+    for krate in crates {
+        analysis.duplicates.merge(krate.duplicates);
+        if opts.per_file {
+            per_file.push((krate.label, krate.instantiations));
         }
     }
 
-    table::print(instantiations, sort_order, function_filter);
+    if opts.per_file {
+        print_reports(&analysis, opts, ownership.as_ref());
+        let merged = regroup(analysis.instantiations, opts);
+        let per_file = per_file
+            .into_iter()
+            .map(|(label, instantiations)| (label, regroup(instantiations, opts)))
+            .collect::<Vec<_>>();
+        table::print_per_file(
+            merged,
+            &per_file,
+            opts.group_by,
+            opts.sort,
+            opts.filter.as_ref(),
+        );
+    } else {
+        print(&analysis, opts, ownership.as_ref());
+    }
     Ok(0)
 }
 
-fn propagate_opts(cmd: &mut Command, opts: &LlvmLines, outfile: &Path) {
+// The files of one crate, such as its codegen units, whose duplicates are
+// combined before those of different crates so that functions they both
+// define count as a single copy.
+struct CrateFiles {
+    // Index of the crate's first file in the input paths.
+    first: usize,
+    label: String,
+    duplicates: Duplicates,
+    // Only with --per-file.
+    instantiations: Map<String, Instantiations>,
+}
+
+// rustc_middle-4d6a46b4d7b0e7b3.rustc_middle.9b1cfa9c4e41ee0a-cgu.07.rcgu.ll -> rustc_middle
+fn crate_name_of_file(path: &Path) -> String {
+    if path == Path::new("-") {
+        return "(stdin)".to_owned();
+    }
+    let file_name = path.file_name().map_or_else(
+        || path.to_string_lossy(),
+        |file_name| file_name.to_string_lossy(),
+    );
+    let stem = file_name.split('.').next().unwrap_or(&file_name);
+    match stem.rsplit_once('-') {
+        Some((crate_name, hash))
+            if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) =>
+        {
+            crate_name.to_owned()
+        }
+        _ => stem.to_owned(),
+    }
+}
+
+// Cargo metadata is only needed by --ownership, and is the same for every
+// table printed.
+fn ownership(opts: &LlvmLines) -> Result<Option<Ownership>> {
+    if opts.ownership {
+        Ok(Some(Ownership::new(&cargo_metadata(opts)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn print(analysis: &Analysis, opts: &LlvmLines, ownership: Option<&Ownership>) {
+    print_reports(analysis, opts, ownership);
+
+    let instantiations = analysis.instantiations.clone();
+    let filter = opts.filter.as_ref();
+    if opts.group_by == GroupBy::Function {
+        if opts.fold_closures {
+            let folded = fold_closures(instantiations);
+            table::print_folded(folded, opts.sort, filter, ownership);
+        } else {
+            table::print(instantiations, opts.sort, filter, ownership);
+        }
+    } else {
+        let groups = regroup(instantiations, opts);
+        table::print_groups(groups, opts.group_by, opts.sort, filter);
+    }
+}
+
+// Prints every report that goes above the function table.
+fn print_reports(analysis: &Analysis, opts: &LlvmLines, ownership: Option<&Ownership>) {
+    for report in &opts.report {
+        // Only the function table is populated from bitcode.
+        let needs_text = !matches!(report, Report::DropGlue | Report::Categories);
+        if needs_text && analysis.bitcode_modules > 0 {
+            let _ = writeln!(
+                io::stderr(),
+                "warning: --report {} needs textual IR; {} bitcode input{} not included",
+                report.to_possible_value().unwrap().get_name(),
+                analysis.bitcode_modules,
+                if analysis.bitcode_modules == 1 {
+                    " is"
+                } else {
+                    "s are"
+                },
+            );
+        }
+        match report {
+            Report::Composition => table::print_composition(&analysis.composition),
+            Report::Globals => table::print_globals(&analysis.globals, opts.sort),
+            Report::Vtables => table::print_vtables(&analysis.vtables, opts.sort),
+            Report::DropGlue => table::print_drop_glue(&analysis.instantiations, opts.sort),
+            Report::Async => table::print_async(analysis, opts.sort),
+            Report::Categories => table::print_categories(analysis, opts.sort),
+            Report::Duplicates => table::print_duplicates(&analysis.duplicates, opts.sort),
+        }
+    }
+
+    if !opts.bucket.is_empty() {
+        table::print_buckets(&analysis.instantiations, &opts.bucket, opts.sort);
+    }
+
+    if let Some(ownership) = ownership {
+        table::print_ownership(&analysis.instantiations, ownership, opts.sort);
+    }
+}
+
+// Totals the instantiations by closure's enclosing function and by trait or
+// impl, as selected by --fold-closures and --group-by.
+fn regroup(
+    mut instantiations: Map<String, Instantiations>,
+    opts: &LlvmLines,
+) -> Map<String, Instantiations> {
+    if opts.fold_closures {
+        instantiations = fold_closures(instantiations)
+            .into_iter()
+            .map(|(name, folded)| (name, folded.total))
+            .collect();
+    }
+    if opts.group_by != GroupBy::Function {
+        instantiations = group_by(instantiations, opts.group_by);
+    }
+    instantiations
+}
+
+fn propagate_opts(
+    cmd: &mut Command,
+    opts: &LlvmLines,
+    selection: Option<&Selection>,
+    outdir: &Path,
+) {
     let LlvmLines {
         // Strip out options that are for cargo-llvm-lines itself.
         sort: _,
         filter: _,
+        group_by: _,
+        fold_closures: _,
+        ownership: _,
+        bucket: _,
+        bucket_file: _,
+        report: _,
+        per_package: _,
+        deps,
         files: _,
+        include: _,
+        exclude_files: _,
+        per_file: _,
         help: _,
         version: _,
 
@@ -154,7 +498,15 @@ fn propagate_opts(cmd: &mut Command, opts: &LlvmLines, outfile: &Path) {
         color,
         ref config,
         ref nightly_only_flags,
-        ref package,
+        // Each selected package or target gets its own cargo rustc invocation.
+        package: _,
+        workspace: _,
+        exclude: _,
+        bins: _,
+        examples: _,
+        tests: _,
+        benches: _,
+        all_targets: _,
         lib,
         ref bin,
         ref example,
@@ -205,10 +557,23 @@ fn propagate_opts(cmd: &mut Command, opts: &LlvmLines, outfile: &Path) {
         cmd.arg(format!("-Z{}", flag));
     }
 
-    if let Some(package) = package {
+    if let Some(package) = selection.and_then(|selection| selection.package.as_ref()) {
         cmd.flag_value("--package", package);
     }
 
+    let target_selection = selection.and_then(|selection| selection.target.as_ref());
+    if let Some(target) = target_selection {
+        match target.kind {
+            TargetKind::Lib => {
+                cmd.arg("--lib");
+            }
+            TargetKind::Bin => cmd.flag_value("--bin", &target.name),
+            TargetKind::Example => cmd.flag_value("--example", &target.name),
+            TargetKind::Test => cmd.flag_value("--test", &target.name),
+            TargetKind::Bench => cmd.flag_value("--bench", &target.name),
+        }
+    }
+
     if lib {
         cmd.arg("--lib");
     }
@@ -245,19 +610,33 @@ fn propagate_opts(cmd: &mut Command, opts: &LlvmLines, outfile: &Path) {
         cmd.flag_value("--jobs", jobs.to_string());
     }
 
-    if release {
-        cmd.arg("--release");
-    }
-
-    if let Some(profile) = profile {
+    if let Some(harness) = target_selection.and_then(|target| target.harness) {
+        // Like `cargo test --release`, which builds unit tests with the
+        // release profile, from which the bench profile inherits.
+        let profile = if release || harness == Harness::Bench {
+            "bench"
+        } else {
+            "test"
+        };
         cmd.flag_value("--profile", profile);
+    } else {
+        if release {
+            cmd.arg("--release");
+        }
+
+        if let Some(profile) = profile {
+            cmd.flag_value("--profile", profile);
+        }
     }
 
     if let Some(target) = target {
         cmd.flag_value("--target", target);
     }
 
-    if let Some(target_dir) = target_dir {
+    if deps {
+        // Dependencies that are already built would not be compiled again.
+        cmd.flag_value("--target-dir", outdir.join("target"));
+    } else if let Some(target_dir) = target_dir {
         cmd.flag_value("--target-dir", target_dir);
     }
 
@@ -287,11 +666,13 @@ fn propagate_opts(cmd: &mut Command, opts: &LlvmLines, outfile: &Path) {
     // will likely cause errors in LLVM. Consider adding -C
     // passes=name-anon-globals to the compiler command line."
     cmd.arg("--");
-    cmd.flag_value("--emit", "llvm-ir");
-    cmd.arg("-Cno-prepopulate-passes");
-    cmd.arg("-Cpasses=name-anon-globals");
-    cmd.arg("-o");
-    cmd.arg(outfile);
+    if !deps {
+        cmd.flag_value("--emit", "llvm-ir");
+        cmd.arg("-Cno-prepopulate-passes");
+        cmd.arg("-Cpasses=name-anon-globals");
+        cmd.arg("-o");
+        cmd.arg(outdir.join("crate"));
+    }
     cmd.args(rest);
 }
 
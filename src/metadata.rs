@@ -0,0 +1,209 @@
+use crate::cmd::CommandExt as _;
+use crate::error::{Error, Result};
+use crate::opts::LlvmLines;
+use serde_derive::Deserialize;
+use std::env;
+use std::ffi::OsString;
+use std::process::{Command, Stdio};
+
+#[derive(Deserialize)]
+pub struct Metadata {
+    pub packages: Vec<Package>,
+    pub workspace_members: Vec<String>,
+    pub resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+pub struct Resolve {
+    pub root: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub id: String,
+    pub name: String,
+    pub version: String,
+    pub targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+pub struct Target {
+    pub name: String,
+    pub kind: Vec<String>,
+    // Whether `cargo test` builds the target as a test harness by default.
+    // Cargo metadata has no equivalent for `cargo bench`.
+    pub test: bool,
+}
+
+impl Metadata {
+    pub fn workspace_packages(&self) -> impl Iterator<Item = &Package> {
+        self.packages
+            .iter()
+            .filter(|package| self.workspace_members.contains(&package.id))
+    }
+
+    /// The package whose manifest cargo would use without `--package`, if
+    /// not a virtual workspace.
+    pub fn root_package(&self) -> Option<&Package> {
+        let root = self.resolve.as_ref()?.root.as_ref()?;
+        self.packages.iter().find(|package| package.id == *root)
+    }
+
+    /// The package that matches a package ID specification, preferring
+    /// workspace members.
+    pub fn package(&self, spec: &str) -> Option<&Package> {
+        self.workspace_packages()
+            .find(|package| package.matches_spec(spec))
+            .or_else(|| {
+                self.packages
+                    .iter()
+                    .find(|package| package.matches_spec(spec))
+            })
+    }
+}
+
+impl Package {
+    /// Whether the package matches a package ID specification as accepted by
+    /// `cargo --package`: `name`, `name@version` with a possibly partial
+    /// version, or a URL like `path+file:///tmp/demo#demo-pkg@0.1.0`.
+    pub fn matches_spec(&self, spec: &str) -> bool {
+        let (url, fragment) = if spec.contains("://") {
+            match spec.split_once('#') {
+                Some((url, fragment)) => (Some(url), Some(fragment)),
+                None => (Some(spec), None),
+            }
+        } else {
+            (None, Some(spec))
+        };
+
+        let (mut name, version) = match fragment {
+            Some(fragment) => match fragment.split_once('@') {
+                Some((name, version)) => (Some(name), Some(version)),
+                // file:///tmp/demo#0.1.0
+                None if url.is_some() && fragment.starts_with(|ch: char| ch.is_ascii_digit()) => {
+                    (None, Some(fragment))
+                }
+                None => (Some(fragment), None),
+            },
+            None => (None, None),
+        };
+        if let Some(url) = url {
+            if !same_source(url, self.source_url()) {
+                return false;
+            }
+            // Without a name, a URL names the package in its last segment.
+            name = name.or_else(|| url.trim_end_matches('/').rsplit('/').next());
+        }
+
+        name.is_none_or(|name| name == self.name)
+            && version.is_none_or(|version| {
+                let mut actual = self.version.split('.');
+                version.split('.').all(|part| actual.next() == Some(part))
+            })
+    }
+
+    // path+file:///tmp/demo#demo-pkg@0.1.0, or in the format of cargo before
+    // 1.77, demo-pkg 0.1.0 (path+file:///tmp/demo).
+    fn source_url(&self) -> &str {
+        match self.id.split_once(" (") {
+            Some((_name_version, source)) => source.trim_end_matches(')'),
+            None => self.id.split('#').next().unwrap_or(&self.id),
+        }
+    }
+}
+
+// Compares URLs with or without the kind of source, like path+ or
+// registry+, and for git sources without the branch or revision.
+fn same_source(a: &str, b: &str) -> bool {
+    fn normalize(url: &str) -> &str {
+        let url = match url.split_once('+') {
+            Some(("path" | "registry" | "git", url)) => url,
+            _ => url,
+        };
+        let url = url.split('?').next().unwrap_or(url);
+        url.trim_end_matches('/')
+    }
+    normalize(a) == normalize(b)
+}
+
+impl Target {
+    /// The name by which rustc refers to this target's crate, which is the
+    /// one that appears in symbol names.
+    pub fn crate_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+pub fn cargo_metadata(opts: &LlvmLines) -> Result<Metadata> {
+    // If cargo-llvm-lines was invoked from cargo, use the cargo that invoked it.
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let mut cmd = Command::new(cargo);
+    cmd.arg("metadata");
+    cmd.flag_value("--format-version", "1");
+
+    for kv in &opts.config {
+        cmd.flag_value("--config", kv);
+    }
+
+    for flag in &opts.nightly_only_flags {
+        cmd.arg(format!("-Z{}", flag));
+    }
+
+    if let Some(manifest_path) = &opts.manifest_path {
+        cmd.flag_value("--manifest-path", manifest_path);
+    }
+
+    if opts.frozen {
+        cmd.arg("--frozen");
+    }
+
+    if opts.locked {
+        cmd.arg("--locked");
+    }
+
+    if opts.offline {
+        cmd.arg("--offline");
+    }
+
+    let output = cmd.stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(Error::Msg("cargo metadata failed"));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+#[test]
+fn test_matches_spec() {
+    let package = |id: &str| Package {
+        id: id.to_owned(),
+        name: "demo-pkg".to_owned(),
+        version: "0.1.2".to_owned(),
+        targets: Vec::new(),
+    };
+    let local = package("path+file:///tmp/llt#demo-pkg@0.1.2");
+    for spec in [
+        "demo-pkg",
+        "demo-pkg@0.1",
+        "demo-pkg@0.1.2",
+        "path+file:///tmp/llt#demo-pkg@0.1.2",
+        "file:///tmp/llt#demo-pkg",
+    ] {
+        assert!(local.matches_spec(spec), "{}", spec);
+    }
+    for spec in [
+        "demo",
+        "demo-pkg@0.2",
+        "demo-pkg@0.1.20",
+        "path+file:///tmp/other#demo-pkg@0.1.2",
+        "file:///tmp/llt",
+    ] {
+        assert!(!local.matches_spec(spec), "{}", spec);
+    }
+
+    let registry = package("registry+https://github.com/rust-lang/crates.io-index#demo-pkg@0.1.2");
+    assert!(registry.matches_spec("https://github.com/rust-lang/crates.io-index#demo-pkg@0.1.2"));
+    let old_format = package("demo-pkg 0.1.2 (path+file:///tmp/demo-pkg)");
+    assert!(old_format.matches_spec("path+file:///tmp/demo-pkg#0.1.2"));
+    assert!(old_format.matches_spec("file:///tmp/demo-pkg"));
+}
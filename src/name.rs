@@ -0,0 +1,87 @@
+/// Splits a demangled `<SelfType as Trait>::method` into its self type, trait,
+/// and the path that follows the qualified type.
+pub fn split_trait_impl(name: &str) -> Option<(&str, &str, &str)> {
+    if !name.starts_with('<') {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut as_keyword = None;
+    let mut prev = '\0';
+    for (i, ch) in name.char_indices() {
+        match ch {
+            '<' => depth += 1,
+            '>' if prev != '-' => {
+                depth -= 1;
+                if depth == 0 {
+                    let (self_ty, trait_path) = as_keyword?;
+                    let rest = &name[i + 1..];
+                    let rest = rest.strip_prefix("::").unwrap_or(rest);
+                    return Some((self_ty, &name[trait_path..i], rest));
+                }
+            }
+            ' ' if depth == 1 && as_keyword.is_none() && name[i..].starts_with(" as ") => {
+                as_keyword = Some((&name[1..i], i + " as ".len()));
+            }
+            _ => {}
+        }
+        prev = ch;
+    }
+
+    None
+}
+
+/// Removes the `[c1f1a4ba060b9bfa]` crate disambiguators that v0 demangling
+/// attaches to crate names, as in `core[c1f1a4ba060b9bfa]::ptr::drop_in_place`.
+pub fn strip_crate_disambiguators(name: &str) -> String {
+    let mut stripped = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(open) = rest.find('[') {
+        let after_ident = rest[..open]
+            .chars()
+            .next_back()
+            .is_some_and(|ch| ch.is_alphanumeric() || ch == '_');
+        let hex = rest[open + 1..]
+            .bytes()
+            .take_while(u8::is_ascii_hexdigit)
+            .count();
+        let is_disambiguator = after_ident && hex > 0 && rest[open + 1 + hex..].starts_with(']');
+        stripped.push_str(&rest[..open]);
+        if is_disambiguator {
+            rest = &rest[open + hex + 2..];
+        } else {
+            stripped.push('[');
+            rest = &rest[open + 1..];
+        }
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[test]
+fn test_split_trait_impl() {
+    assert_eq!(
+        split_trait_impl("<a::Sq as a::Shape>::area"),
+        Some(("a::Sq", "a::Shape", "area")),
+    );
+    assert_eq!(
+        split_trait_impl("<fn() -> u8 as core::fmt::Debug>::fmt"),
+        Some(("fn() -> u8", "core::fmt::Debug", "fmt")),
+    );
+    assert_eq!(
+        split_trait_impl("<alloc::vec::Vec<T,A> as core::ops::drop::Drop>::drop"),
+        Some(("alloc::vec::Vec<T,A>", "core::ops::drop::Drop", "drop")),
+    );
+    assert_eq!(split_trait_impl("<[T]>::len"), None);
+    assert_eq!(split_trait_impl("core::ptr::drop_in_place<T>"), None);
+}
+
+#[test]
+fn test_strip_crate_disambiguators() {
+    assert_eq!(
+        strip_crate_disambiguators(
+            "core[c1f1a4ba060b9bfa]::ptr::drop_in_place::<[alloc[fdfd2bd8633a6659]::string::String; 1usize]>",
+        ),
+        "core::ptr::drop_in_place::<[alloc::string::String; 1usize]>",
+    );
+}
@@ -1,4 +1,6 @@
+use crate::bucket::Bucket;
 use clap::{Parser, ValueEnum};
+use glob::Pattern;
 use regex::Regex;
 use std::ffi::OsString;
 use std::path::PathBuf;
@@ -26,6 +28,10 @@ const FEATURE_SELECTION: &str = "Feature Selection";
 const COMPILATION_OPTIONS: &str = "Compilation Options";
 const MANIFEST_OPTIONS: &str = "Manifest Options";
 
+// Options that select one target, which cannot be combined with the options
+// that select every target of some kind.
+const SINGLE_TARGET: [&str; 5] = ["lib", "bin", "example", "test", "bench"];
+
 #[derive(Parser, Debug)]
 #[command(
     name = "cargo-llvm-lines",
@@ -67,11 +73,76 @@ pub struct LlvmLines {
     #[arg(long, value_name = "REGEX")]
     pub filter: Option<Regex>,
 
-    /// Analyze existing .ll files that were produced by e.g.
+    /// Total the lines of trait methods by trait or by impl.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "GROUP",
+        default_value_t = GroupBy::Function,
+    )]
+    pub group_by: GroupBy,
+
+    /// Attribute closures to the function they are defined in.
+    #[arg(long)]
+    pub fold_closures: bool,
+
+    /// Classify functions as belonging to the workspace, a dependency, or the
+    /// standard library.
+    #[arg(long)]
+    pub ownership: bool,
+
+    /// Total the lines of functions whose name matches REGEX into a bucket
+    /// called NAME. The first matching bucket wins. May be given more than
+    /// once.
+    #[arg(long, value_name = "NAME=REGEX")]
+    pub bucket: Vec<Bucket>,
+
+    /// Read additional buckets from a file containing one NAME=REGEX per
+    /// line.
+    #[arg(long, value_name = "PATH")]
+    pub bucket_file: Option<PathBuf>,
+
+    /// Print an additional report above the function table. May be given
+    /// more than once.
+    #[arg(long, value_enum, value_name = "REPORT")]
+    pub report: Vec<Report>,
+
+    /// When more than one package is selected, print a separate table for
+    /// each package instead of a single table merged across packages.
+    #[arg(long)]
+    pub per_package: bool,
+
+    /// Also count the LLVM IR of every dependency, by building them all from
+    /// scratch in a temporary target directory.
+    #[arg(long, conflicts_with = "target_dir")]
+    pub deps: bool,
+
+    /// Analyze existing .ll or .bc files that were produced by e.g.
     /// `RUSTFLAGS="--emit=llvm-ir" ./x.py build --stage 0 compiler/rustc`.
+    /// Directories are searched recursively, without following symlinks to
+    /// directories, glob patterns are expanded, and `-` reads from standard
+    /// input.
     #[arg(short, long, value_name = "FILES")]
     pub files: Vec<PathBuf>,
 
+    /// With --files, analyze only the files in directories whose name
+    /// matches GLOB. Defaults to `*.ll`, `*.ll.gz`, `*.ll.zst`, and `*.bc`
+    /// where there is no IR file of the same name. May be given more than
+    /// once.
+    #[arg(long, value_name = "GLOB", requires = "files")]
+    pub include: Vec<Pattern>,
+
+    /// With --files, skip the files that match GLOB. May be given more than
+    /// once.
+    #[arg(long, value_name = "GLOB", requires = "files")]
+    pub exclude_files: Vec<Pattern>,
+
+    /// With --files, print the lines in each input file as a separate column.
+    /// Files named after the same crate, such as the codegen units of one
+    /// crate, share a column.
+    #[arg(long, requires = "files")]
+    pub per_file: bool,
+
     // The following options are passed through to the cargo rustc invocation.
     #[arg(long)]
     pub verbose: bool,
@@ -90,7 +161,11 @@ pub struct LlvmLines {
 
     // Package selection
     #[arg(short, long, value_name = "SPEC", help_heading = PACKAGE_SELECTION)]
-    pub package: Option<String>,
+    pub package: Vec<String>,
+    #[arg(long, help_heading = PACKAGE_SELECTION)]
+    pub workspace: bool,
+    #[arg(long, value_name = "SPEC", requires = "workspace", help_heading = PACKAGE_SELECTION)]
+    pub exclude: Vec<String>,
 
     // Target selection
     #[arg(long, help_heading = TARGET_SELECTION)]
@@ -103,6 +178,16 @@ pub struct LlvmLines {
     pub test: Option<String>,
     #[arg(long, value_name = "NAME", help_heading = TARGET_SELECTION)]
     pub bench: Option<String>,
+    #[arg(long, conflicts_with_all = SINGLE_TARGET, help_heading = TARGET_SELECTION)]
+    pub bins: bool,
+    #[arg(long, conflicts_with_all = SINGLE_TARGET, help_heading = TARGET_SELECTION)]
+    pub examples: bool,
+    #[arg(long, conflicts_with_all = SINGLE_TARGET, help_heading = TARGET_SELECTION)]
+    pub tests: bool,
+    #[arg(long, conflicts_with_all = SINGLE_TARGET, help_heading = TARGET_SELECTION)]
+    pub benches: bool,
+    #[arg(long, conflicts_with_all = SINGLE_TARGET, help_heading = TARGET_SELECTION)]
+    pub all_targets: bool,
 
     // Feature selection
     #[arg(short = 'F', long, value_name = "FEATURES", help_heading = FEATURE_SELECTION)]
@@ -113,6 +198,8 @@ pub struct LlvmLines {
     pub no_default_features: bool,
 
     // Compilation options
+    /// Number of parallel jobs. With --files, the number of files to analyze
+    /// at the same time, which defaults to the number of CPUs.
     #[arg(short, long, value_name = "N", help_heading = COMPILATION_OPTIONS)]
     pub jobs: Option<usize>,
     #[arg(long, help_heading = COMPILATION_OPTIONS)]
@@ -146,6 +233,35 @@ pub enum SortOrder {
     Name,
 }
 
+#[derive(ValueEnum, Copy, Clone, PartialEq, Debug)]
+pub enum GroupBy {
+    /// One row per function
+    Function,
+    /// One row per trait, such as `core::fmt::Debug`
+    Trait,
+    /// One row per impl, such as `<T as core::fmt::Debug>`
+    Impl,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug)]
+pub enum Report {
+    /// Lines and bytes of the whole .ll file by kind of top-level entity
+    Composition,
+    /// Bytes of static data grouped by owner
+    Globals,
+    /// Trait object vtables and the functions they point to, by type and trait
+    Vtables,
+    /// Lines of `drop_in_place` drop glue by the type being dropped
+    DropGlue,
+    /// Poll function lines, states, and drop glue of each async fn and block
+    Async,
+    /// Lines by common source of bloat, such as drop glue or formatting
+    Categories,
+    /// Functions instantiated by more than one crate or input file, and
+    /// whether -Zshare-generics could dedupe them
+    Duplicates,
+}
+
 #[derive(ValueEnum, Debug, Clone, Copy)]
 pub enum Coloring {
     Auto,
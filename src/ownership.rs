@@ -0,0 +1,146 @@
+use crate::count::Instantiations;
+use crate::metadata::{Metadata, Target};
+use crate::name::split_trait_impl;
+use std::collections::{BTreeSet as Set, HashMap as Map};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Owner {
+    Workspace,
+    Dependency,
+    Std,
+}
+
+impl Owner {
+    pub fn name(self) -> &'static str {
+        match self {
+            Owner::Workspace => "workspace",
+            Owner::Dependency => "dependency",
+            Owner::Std => "std",
+        }
+    }
+}
+
+/// Which crates are part of the workspace and which are third-party, as
+/// reported by `cargo metadata`. Any other crate is assumed to be part of the
+/// standard library or its private dependencies.
+pub struct Ownership {
+    workspace: Set<String>,
+    dependencies: Set<String>,
+}
+
+impl Ownership {
+    pub fn new(metadata: &Metadata) -> Self {
+        let mut workspace = Set::new();
+        let mut dependencies = Set::new();
+        for package in &metadata.packages {
+            let crates = if metadata.workspace_members.contains(&package.id) {
+                &mut workspace
+            } else {
+                &mut dependencies
+            };
+            crates.extend(package.targets.iter().map(Target::crate_name));
+        }
+        for std_crate in ["std", "core", "alloc", "proc_macro", "test"] {
+            dependencies.remove(std_crate);
+        }
+        Ownership {
+            workspace,
+            dependencies,
+        }
+    }
+
+    /// A trait impl belongs to whichever of the self type's crate or the
+    /// trait's crate is closest to the workspace, since only those two crates
+    /// are allowed to contain the impl.
+    pub fn owner(&self, name: &str) -> Owner {
+        match split_trait_impl(name) {
+            Some((self_ty, trait_path, _method)) => {
+                self.crate_owner(self_ty).min(self.crate_owner(trait_path))
+            }
+            None => self.crate_owner(name),
+        }
+    }
+
+    fn crate_owner(&self, path: &str) -> Owner {
+        let path = path.trim_start_matches(['<', '&', '*', '[', '(']);
+        let path = ["mut ", "const ", "dyn "]
+            .iter()
+            .fold(path, |path, prefix| {
+                path.strip_prefix(prefix).unwrap_or(path)
+            });
+        let end = path
+            .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+            .unwrap_or(path.len());
+        let krate = &path[..end];
+        if self.workspace.contains(krate) {
+            Owner::Workspace
+        } else if self.dependencies.contains(krate) {
+            Owner::Dependency
+        } else {
+            Owner::Std
+        }
+    }
+
+    pub fn totals(
+        &self,
+        instantiations: &Map<String, Instantiations>,
+    ) -> Map<Owner, Instantiations> {
+        let mut totals = Map::<Owner, Instantiations>::new();
+        for owner in [Owner::Workspace, Owner::Dependency, Owner::Std] {
+            totals.insert(owner, Instantiations::default());
+        }
+        for (name, instantiations) in instantiations {
+            totals
+                .get_mut(&self.owner(name))
+                .unwrap()
+                .merge(instantiations);
+        }
+        totals
+    }
+}
+
+#[test]
+fn test_owner() {
+    let metadata = r#"{
+        "packages": [
+            {
+                "id": "path+file:///ws#demo-pkg@0.1.0",
+                "name": "demo-pkg",
+                "version": "0.1.0",
+                "targets": [{"name": "demo-pkg", "kind": ["lib"], "test": true}]
+            },
+            {
+                "id": "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.0",
+                "name": "serde",
+                "version": "1.0.0",
+                "targets": [{"name": "serde", "kind": ["lib"], "test": true}]
+            }
+        ],
+        "workspace_members": ["path+file:///ws#demo-pkg@0.1.0"],
+        "resolve": null
+    }"#;
+    let metadata: Metadata = serde_json::from_str(metadata).unwrap();
+    let ownership = Ownership::new(&metadata);
+
+    let cases = [
+        ("demo_pkg::run", Owner::Workspace),
+        ("serde::de::Visitor::visit_str", Owner::Dependency),
+        ("core::ptr::drop_in_place<demo_pkg::Thing>", Owner::Std),
+        ("hashbrown::raw::RawTable<T>::insert", Owner::Std),
+        (
+            "<demo_pkg::Thing as core::fmt::Debug>::fmt",
+            Owner::Workspace,
+        ),
+        (
+            "<alloc::vec::Vec<u8> as serde::Serialize>::serialize",
+            Owner::Dependency,
+        ),
+        (
+            "<&mut serde::de::Unexpected as core::fmt::Display>::fmt",
+            Owner::Dependency,
+        ),
+    ];
+    for (name, owner) in cases {
+        assert_eq!(ownership.owner(name), owner, "{}", name);
+    }
+}
@@ -0,0 +1,333 @@
+use crate::error::{Error, Result};
+use crate::metadata::{cargo_metadata, Metadata, Package, Target};
+use crate::opts::LlvmLines;
+
+/// What a single `cargo rustc` invocation compiles. Cargo only passes the
+/// extra arguments after `--` to one target, so analyzing several packages
+/// or targets takes one invocation each.
+pub struct Selection {
+    pub label: String,
+    pub package: Option<String>,
+    pub target: Option<TargetSelection>,
+}
+
+pub struct TargetSelection {
+    pub kind: TargetKind,
+    pub name: String,
+    pub harness: Option<Harness>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+}
+
+/// The profile with which a lib, bin or example is built as a test harness,
+/// the way `cargo test` or `cargo bench` builds its unit tests.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Harness {
+    Test,
+    Bench,
+}
+
+impl TargetKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetKind::Lib => "lib",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+        }
+    }
+
+    fn of(target: &Target) -> Option<Self> {
+        let kind = target.kind.first()?;
+        match kind.as_str() {
+            "bin" => Some(TargetKind::Bin),
+            "example" => Some(TargetKind::Example),
+            "test" => Some(TargetKind::Test),
+            "bench" => Some(TargetKind::Bench),
+            "custom-build" => None,
+            // lib, rlib, dylib, cdylib, staticlib, proc-macro
+            _ => Some(TargetKind::Lib),
+        }
+    }
+}
+
+/// Whether the options ask for every target of some kind, in which case each
+/// target is reported separately.
+pub fn enumerates_targets(opts: &LlvmLines) -> bool {
+    opts.all_targets || opts.bins || opts.examples || opts.tests || opts.benches
+}
+
+pub fn selections(opts: &LlvmLines) -> Result<Vec<Selection>> {
+    let single_target = opts.lib
+        || opts.bin.is_some()
+        || opts.example.is_some()
+        || opts.test.is_some()
+        || opts.bench.is_some();
+    if single_target && !opts.workspace {
+        return Ok(opts
+            .package
+            .iter()
+            .map(|package| Selection {
+                label: package.clone(),
+                package: Some(package.clone()),
+                target: None,
+            })
+            .collect());
+    }
+
+    let metadata = cargo_metadata(opts)?;
+    if !enumerates_targets(opts) {
+        if !opts.workspace && opts.package.is_empty() {
+            // Without a root package, cargo reports that one needs to be
+            // selected.
+            let Some(package) = metadata.root_package() else {
+                return Ok(Vec::new());
+            };
+            let target = if single_target {
+                None
+            } else {
+                default_target(package)?
+            };
+            return Ok(vec![Selection {
+                label: package.name.clone(),
+                package: None,
+                target,
+            }]);
+        }
+        return selected_packages(opts, &metadata)?
+            .into_iter()
+            .map(|(package, spec)| {
+                let target = if single_target {
+                    None
+                } else {
+                    default_target(package)?
+                };
+                Ok(Selection {
+                    label: package.name.clone(),
+                    package: Some(spec),
+                    target,
+                })
+            })
+            .collect();
+    }
+
+    let packages = selected_packages(opts, &metadata)?;
+    let mut selections = Vec::new();
+    let multiple_packages = packages.len() > 1;
+    for (package, spec) in packages {
+        for target in &package.targets {
+            let Some(kind) = TargetKind::of(target) else {
+                continue;
+            };
+            for harness in target_builds(opts, kind, target) {
+                let mut label = format!("{} {}", kind.name(), target.name);
+                match harness {
+                    Some(Harness::Test) => label.push_str(" (test)"),
+                    Some(Harness::Bench) => label.push_str(" (bench)"),
+                    None => {}
+                }
+                if multiple_packages {
+                    label = format!("{} {}", package.name, label);
+                }
+                selections.push(Selection {
+                    label,
+                    package: Some(spec.clone()),
+                    target: Some(TargetSelection {
+                        kind,
+                        name: target.name.clone(),
+                        harness,
+                    }),
+                });
+            }
+        }
+    }
+
+    if selections.is_empty() {
+        return Err(Error::Msg("no targets matched the selected target kinds"));
+    }
+    let unit_tests = selections.iter().any(|selection| {
+        selection
+            .target
+            .as_ref()
+            .is_some_and(|target| target.harness.is_some())
+    });
+    if unit_tests && opts.profile.is_some() {
+        return Err(Error::Msg(
+            "unit tests are built with the test or bench profile, which cannot be combined with --profile",
+        ));
+    }
+    Ok(selections)
+}
+
+// How each target is built for the target selection options, following
+// cargo: --tests includes the unit tests of the targets that have `test =
+// true`, --benches those of the lib and bins, for which `bench = true` is
+// the default but not reported by cargo metadata, and --all-targets builds
+// the unit tests in addition to the targets themselves.
+fn target_builds(opts: &LlvmLines, kind: TargetKind, target: &Target) -> Vec<Option<Harness>> {
+    let mut builds = Vec::new();
+    let selected = match kind {
+        TargetKind::Lib => opts.all_targets,
+        TargetKind::Bin => opts.all_targets || opts.bins,
+        TargetKind::Example => opts.all_targets || opts.examples,
+        TargetKind::Test => opts.all_targets || opts.tests,
+        TargetKind::Bench => opts.all_targets || opts.benches,
+    };
+    if selected {
+        builds.push(None);
+    }
+    if let TargetKind::Lib | TargetKind::Bin | TargetKind::Example = kind {
+        // With both --tests and --benches, cargo builds the unit tests once.
+        if target.test && (opts.all_targets || opts.tests) {
+            builds.push(Some(Harness::Test));
+        } else if kind != TargetKind::Example && (opts.all_targets || opts.benches) {
+            builds.push(Some(Harness::Bench));
+        }
+    }
+    builds
+}
+
+// Cargo builds the lib and the bins of a package by default, but passes
+// rustc arguments to only one target. If there are several, analyze the lib.
+fn default_target(package: &Package) -> Result<Option<TargetSelection>> {
+    let mut lib = None;
+    let mut defaults = 0;
+    for target in &package.targets {
+        match TargetKind::of(target) {
+            Some(TargetKind::Lib) => {
+                lib = Some(target);
+                defaults += 1;
+            }
+            Some(TargetKind::Bin) => defaults += 1,
+            _ => {}
+        }
+    }
+
+    if defaults <= 1 {
+        return Ok(None);
+    }
+
+    if let Some(lib) = lib {
+        return Ok(Some(TargetSelection {
+            kind: TargetKind::Lib,
+            name: lib.name.clone(),
+            harness: None,
+        }));
+    }
+
+    let flags = package
+        .targets
+        .iter()
+        .filter_map(|target| {
+            let kind = TargetKind::of(target)?;
+            Some(match kind {
+                TargetKind::Lib => "--lib".to_owned(),
+                _ => format!("--{} {}", kind.name(), target.name),
+            })
+        })
+        .collect();
+    Err(Error::MultipleTargets(package.name.clone(), flags))
+}
+
+// The selected packages, each with the package ID specification by which to
+// pass it to cargo. Specs given by --package are passed on unchanged.
+fn selected_packages<'a>(
+    opts: &LlvmLines,
+    metadata: &'a Metadata,
+) -> Result<Vec<(&'a Package, String)>> {
+    if opts.workspace {
+        let mut packages: Vec<(&Package, String)> = metadata
+            .workspace_packages()
+            .filter(|package| !opts.exclude.iter().any(|spec| package.matches_spec(spec)))
+            .map(|package| (package, package.name.clone()))
+            .collect();
+        packages.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name));
+        return Ok(packages);
+    }
+
+    if opts.package.is_empty() {
+        return match metadata.root_package() {
+            Some(package) => Ok(vec![(package, package.name.clone())]),
+            None => Err(Error::Msg(
+                "a virtual workspace requires --package or --workspace to select targets",
+            )),
+        };
+    }
+
+    opts.package
+        .iter()
+        .map(|spec| {
+            let package = metadata.package(spec).ok_or(Error::Msg(
+                "package ID specification did not match any package",
+            ))?;
+            Ok((package, spec.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn builds(args: &[&str], kind: TargetKind, test: bool) -> Vec<Option<Harness>> {
+    use crate::opts::Subcommand;
+    use clap::Parser as _;
+
+    let args = ["cargo", "llvm-lines"].iter().chain(args);
+    let Subcommand::LlvmLines(opts) = Subcommand::parse_from(args);
+    let target = Target {
+        name: "demo".to_owned(),
+        kind: vec![kind.name().to_owned()],
+        test,
+    };
+    target_builds(&opts, kind, &target)
+}
+
+#[test]
+fn test_tests() {
+    let unit = [Some(Harness::Test)];
+    assert_eq!(builds(&["--tests"], TargetKind::Lib, true), unit);
+    assert_eq!(builds(&["--tests"], TargetKind::Bin, true), unit);
+    assert_eq!(builds(&["--tests"], TargetKind::Bin, false), []);
+    assert_eq!(builds(&["--tests"], TargetKind::Example, false), []);
+    assert_eq!(builds(&["--tests"], TargetKind::Test, true), [None]);
+    assert_eq!(builds(&["--tests"], TargetKind::Bench, false), []);
+}
+
+#[test]
+fn test_benches() {
+    let unit = [Some(Harness::Bench)];
+    assert_eq!(builds(&["--benches"], TargetKind::Lib, true), unit);
+    assert_eq!(builds(&["--benches"], TargetKind::Bin, false), unit);
+    assert_eq!(builds(&["--benches"], TargetKind::Example, false), []);
+    assert_eq!(builds(&["--benches"], TargetKind::Test, true), []);
+    assert_eq!(builds(&["--benches"], TargetKind::Bench, false), [None]);
+    let both = ["--tests", "--benches"];
+    assert_eq!(builds(&both, TargetKind::Lib, true), [Some(Harness::Test)]);
+}
+
+#[test]
+fn test_all_targets() {
+    let args = ["--all-targets"];
+    let unit = [None, Some(Harness::Test)];
+    assert_eq!(builds(&args, TargetKind::Lib, true), unit);
+    assert_eq!(builds(&args, TargetKind::Bin, true), unit);
+    let bench = [None, Some(Harness::Bench)];
+    assert_eq!(builds(&args, TargetKind::Bin, false), bench);
+    assert_eq!(builds(&args, TargetKind::Example, false), [None]);
+    assert_eq!(builds(&args, TargetKind::Test, true), [None]);
+    assert_eq!(builds(&args, TargetKind::Bench, false), [None]);
+}
+
+#[test]
+fn test_bins_examples() {
+    assert_eq!(builds(&["--bins"], TargetKind::Lib, true), []);
+    assert_eq!(builds(&["--bins"], TargetKind::Bin, true), [None]);
+    assert_eq!(builds(&["--examples"], TargetKind::Example, false), [None]);
+    assert_eq!(builds(&["--examples"], TargetKind::Bin, true), []);
+}
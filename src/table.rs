@@ -1,66 +1,574 @@
-use crate::opts::SortOrder;
+use crate::bucket::{bucket_totals, Bucket};
+use crate::category::categorize_all;
+use crate::closure::Folded;
+use crate::count::{Analysis, Composition, Section};
+use crate::drop::drop_glue_by_type;
+use crate::duplicate::{Duplicates, Sharing};
+use crate::global::Globals;
+use crate::opts::{GroupBy, SortOrder};
+use crate::ownership::Ownership;
+use crate::vtable::Vtables;
 use crate::Instantiations;
 use regex::Regex;
 use std::collections::HashMap as Map;
+use std::fmt::Write as _;
 use std::io::{self, Write};
 
+pub(crate) struct Row {
+    pub name: String,
+    pub size: usize,
+    pub copies: usize,
+    // Values for the additional columns, if any, that are printed between the
+    // copies and the name.
+    pub columns: Vec<String>,
+}
+
+pub(crate) struct Column {
+    pub heading: String,
+    pub total: String,
+    pub align: Align,
+}
+
+#[derive(Copy, Clone)]
+pub(crate) enum Align {
+    Left,
+    Right,
+}
+
+pub(crate) struct Headings {
+    pub size: &'static str,
+    pub copies: &'static str,
+    pub name: &'static str,
+}
+
+const FUNCTIONS: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Function name",
+};
+
+const GLOBALS: Headings = Headings {
+    size: "Bytes",
+    copies: "Globals",
+    name: "Owner",
+};
+
+const VTABLES: Headings = Headings {
+    size: "Lines",
+    copies: "Vtables",
+    name: "Type and trait",
+};
+
+const ASYNC: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Future",
+};
+
+const PACKAGES: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Package",
+};
+
+const TARGETS: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Target",
+};
+
+const CRATES: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Crate",
+};
+
+const DUPLICATES: Headings = Headings {
+    size: "Lines",
+    copies: "Crates",
+    name: "Function",
+};
+
+const BUCKETS: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Bucket",
+};
+
+const OWNERSHIP: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Owner",
+};
+
+const CATEGORIES: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Category",
+};
+
+const DROP_GLUE: Headings = Headings {
+    size: "Lines",
+    copies: "Copies",
+    name: "Dropped type",
+};
+
 pub(crate) fn print(
     instantiations: Map<String, Instantiations>,
     sort_order: SortOrder,
     function_filter: Option<&Regex>,
+    ownership: Option<&Ownership>,
 ) {
-    let mut data = instantiations.into_iter().collect::<Vec<_>>();
+    let rows = instantiations
+        .into_iter()
+        .map(|(name, instantiations)| {
+            let columns = ownership_column_value(ownership, &name);
+            Row {
+                name,
+                size: instantiations.total_lines,
+                copies: instantiations.copies,
+                columns,
+            }
+        })
+        .collect();
+    let columns = ownership_column(ownership);
+    print_table(rows, sort_order, function_filter, &FUNCTIONS, &columns);
+}
+
+fn ownership_column(ownership: Option<&Ownership>) -> Vec<Column> {
+    match ownership {
+        Some(_) => vec![Column {
+            heading: "Owner".to_owned(),
+            total: String::new(),
+            align: Align::Left,
+        }],
+        None => Vec::new(),
+    }
+}
+
+fn ownership_column_value(ownership: Option<&Ownership>, name: &str) -> Vec<String> {
+    match ownership {
+        Some(ownership) => vec![ownership.owner(name).name().to_owned()],
+        None => Vec::new(),
+    }
+}
+
+pub(crate) fn print_groups(
+    groups: Map<String, Instantiations>,
+    group_by: GroupBy,
+    sort_order: SortOrder,
+    filter: Option<&Regex>,
+) {
+    let rows = groups
+        .into_iter()
+        .map(|(name, instantiations)| Row {
+            name,
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_table(rows, sort_order, filter, &group_headings(group_by), &[]);
+}
+
+fn group_headings(group_by: GroupBy) -> Headings {
+    Headings {
+        name: match group_by {
+            GroupBy::Function => "Function name",
+            GroupBy::Trait => "Trait",
+            GroupBy::Impl => "Impl",
+        },
+        ..FUNCTIONS
+    }
+}
+
+pub(crate) fn print_per_file(
+    instantiations: Map<String, Instantiations>,
+    per_file: &[(String, Map<String, Instantiations>)],
+    group_by: GroupBy,
+    sort_order: SortOrder,
+    filter: Option<&Regex>,
+) {
+    let rows = instantiations
+        .into_iter()
+        .map(|(name, instantiations)| {
+            let columns = per_file
+                .iter()
+                .map(|(_file, file_instantiations)| {
+                    file_instantiations
+                        .get(&name)
+                        .map_or_else(String::new, |x| x.total_lines.to_string())
+                })
+                .collect();
+            Row {
+                name,
+                size: instantiations.total_lines,
+                copies: instantiations.copies,
+                columns,
+            }
+        })
+        .collect();
+    let columns: Vec<Column> = per_file
+        .iter()
+        .map(|(file, file_instantiations)| Column {
+            heading: file.clone(),
+            total: file_instantiations
+                .values()
+                .map(|x| x.total_lines)
+                .sum::<usize>()
+                .to_string(),
+            align: Align::Right,
+        })
+        .collect();
+    print_table(
+        rows,
+        sort_order,
+        filter,
+        &group_headings(group_by),
+        &columns,
+    );
+}
+
+pub(crate) fn print_folded(
+    folded: Map<String, Folded>,
+    sort_order: SortOrder,
+    function_filter: Option<&Regex>,
+    ownership: Option<&Ownership>,
+) {
+    let mut closure_lines = 0;
+    let rows = folded
+        .into_iter()
+        .map(|(name, folded)| {
+            closure_lines += folded.closures.total_lines;
+            let mut columns = vec![folded.closures.total_lines.to_string()];
+            columns.extend(ownership_column_value(ownership, &name));
+            Row {
+                name,
+                size: folded.total.total_lines,
+                copies: folded.total.copies,
+                columns,
+            }
+        })
+        .collect();
+    let mut columns = vec![Column {
+        heading: "Closures".to_owned(),
+        total: closure_lines.to_string(),
+        align: Align::Right,
+    }];
+    columns.extend(ownership_column(ownership));
+    print_table(rows, sort_order, function_filter, &FUNCTIONS, &columns);
+}
+
+pub(crate) fn print_ownership(
+    instantiations: &Map<String, Instantiations>,
+    ownership: &Ownership,
+    sort_order: SortOrder,
+) {
+    let rows = ownership
+        .totals(instantiations)
+        .into_iter()
+        .map(|(owner, instantiations)| Row {
+            name: owner.name().to_owned(),
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &OWNERSHIP);
+}
+
+pub(crate) fn print_globals(globals: &Globals, sort_order: SortOrder) {
+    let rows = globals
+        .by_owner
+        .iter()
+        .map(|(owner, data)| Row {
+            name: owner.clone(),
+            size: data.bytes,
+            copies: data.count,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &GLOBALS);
+}
 
-    let mut total = Instantiations {
+pub(crate) fn print_vtables(vtables: &Vtables, sort_order: SortOrder) {
+    let rows = vtables
+        .groups()
+        .into_iter()
+        .map(|(name, group)| Row {
+            name,
+            size: group.lines,
+            copies: group.vtables,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &VTABLES);
+}
+
+pub(crate) fn print_drop_glue(instantiations: &Map<String, Instantiations>, sort_order: SortOrder) {
+    let rows = drop_glue_by_type(instantiations)
+        .into_iter()
+        .map(|(ty, instantiations)| Row {
+            name: ty,
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &DROP_GLUE);
+}
+
+pub(crate) fn print_async(analysis: &Analysis, sort_order: SortOrder) {
+    let mut total_states = 0;
+    let mut total_drop_glue_lines = 0;
+    let rows = analysis
+        .futures
+        .report(&analysis.instantiations)
+        .into_iter()
+        .map(|future| {
+            total_states += future.states;
+            total_drop_glue_lines += future.drop_glue_lines;
+            Row {
+                name: future.name,
+                size: future.poll_lines,
+                copies: future.copies,
+                columns: vec![
+                    future.states.to_string(),
+                    future.drop_glue_lines.to_string(),
+                ],
+            }
+        })
+        .collect();
+    let columns = [
+        Column {
+            heading: "States".to_owned(),
+            total: total_states.to_string(),
+            align: Align::Right,
+        },
+        Column {
+            heading: "Drop glue".to_owned(),
+            total: total_drop_glue_lines.to_string(),
+            align: Align::Right,
+        },
+    ];
+    print_table(rows, sort_order, None, &ASYNC, &columns);
+    let _ = writeln!(io::stdout());
+}
+
+pub(crate) fn print_duplicates(duplicates: &Duplicates, sort_order: SortOrder) {
+    let mut sharing = Vec::<Sharing>::new();
+    let rows = duplicates
+        .report()
+        .into_iter()
+        .map(|duplicate| {
+            if !sharing.contains(&duplicate.sharing) {
+                sharing.push(duplicate.sharing);
+            }
+            Row {
+                name: duplicate.name,
+                size: duplicate.duplicated_lines,
+                copies: duplicate.crates,
+                columns: vec![duplicate.sharing.name().to_owned()],
+            }
+        })
+        .collect();
+    let columns = [Column {
+        heading: "Sharing".to_owned(),
+        total: String::new(),
+        align: Align::Left,
+    }];
+    print_table(rows, sort_order, None, &DUPLICATES, &columns);
+
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout);
+    let _ = writeln!(stdout, "  Lines are those of every copy beyond the first.");
+    for sharing in sharing {
+        let _ = writeln!(stdout, "  {}: {}", sharing.name(), sharing.explanation());
+    }
+    if duplicates.unidentified() > 0 {
+        let _ = writeln!(
+            stdout,
+            "  {} functions have neither debug info nor a v0 symbol. Copies of their generic\n  instantiations in different crates cannot be matched; build with debuginfo\n  or -Csymbol-mangling-version=v0 to include them.",
+            duplicates.unidentified(),
+        );
+    }
+    let _ = writeln!(stdout);
+}
+
+pub(crate) fn print_categories(analysis: &Analysis, sort_order: SortOrder) {
+    let rows = categorize_all(&analysis.instantiations, &analysis.futures)
+        .into_iter()
+        .map(|(category, instantiations)| Row {
+            name: category.name().to_owned(),
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &CATEGORIES);
+}
+
+pub(crate) fn print_buckets(
+    instantiations: &Map<String, Instantiations>,
+    buckets: &[Bucket],
+    sort_order: SortOrder,
+) {
+    let rows = bucket_totals(instantiations, buckets)
+        .into_iter()
+        .map(|(bucket, instantiations)| Row {
+            name: bucket,
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &BUCKETS);
+}
+
+pub(crate) fn print_selections(
+    selections: Vec<(String, Instantiations)>,
+    sort_order: SortOrder,
+    per_target: bool,
+) {
+    let rows = selections
+        .into_iter()
+        .map(|(label, instantiations)| Row {
+            name: label,
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    let headings = if per_target { &TARGETS } else { &PACKAGES };
+    print_report(rows, sort_order, headings);
+}
+
+pub(crate) fn print_crates(crates: Vec<(String, Instantiations)>, sort_order: SortOrder) {
+    let rows = crates
+        .into_iter()
+        .map(|(crate_name, instantiations)| Row {
+            name: crate_name,
+            size: instantiations.total_lines,
+            copies: instantiations.copies,
+            columns: Vec::new(),
+        })
+        .collect();
+    print_report(rows, sort_order, &CRATES);
+}
+
+pub(crate) fn print_report(rows: Vec<Row>, sort_order: SortOrder, headings: &Headings) {
+    print_table(rows, sort_order, None, headings, &[]);
+    let _ = writeln!(io::stdout());
+}
+
+pub(crate) fn print_table(
+    mut data: Vec<Row>,
+    sort_order: SortOrder,
+    filter: Option<&Regex>,
+    headings: &Headings,
+    columns: &[Column],
+) {
+    let mut total = Row {
+        name: String::new(),
+        size: 0,
         copies: 0,
-        total_lines: 0,
+        columns: Vec::new(),
     };
     for row in &data {
-        total.copies += row.1.copies;
-        total.total_lines += row.1.total_lines;
+        total.copies += row.copies;
+        total.size += row.size;
     }
 
     match sort_order {
         SortOrder::Lines => {
             data.sort_by(|a, b| {
-                let key_lo = (b.1.total_lines, b.1.copies, &a.0);
-                let key_hi = (a.1.total_lines, a.1.copies, &b.0);
+                let key_lo = (b.size, b.copies, &a.name);
+                let key_hi = (a.size, a.copies, &b.name);
                 key_lo.cmp(&key_hi)
             });
         }
         SortOrder::Copies => {
             data.sort_by(|a, b| {
-                let key_lo = (b.1.copies, b.1.total_lines, &a.0);
-                let key_hi = (a.1.copies, a.1.total_lines, &b.0);
+                let key_lo = (b.copies, b.size, &a.name);
+                let key_hi = (a.copies, a.size, &b.name);
                 key_lo.cmp(&key_hi)
             });
         }
         SortOrder::Name => data.sort_by(|a, b| {
-            let key_lo = (&a.0, b.1.copies, b.1.total_lines);
-            let key_hi = (&b.0, a.1.copies, b.1.total_lines);
+            let key_lo = (&a.name, b.copies, b.size);
+            let key_hi = (&b.name, a.copies, b.size);
             key_lo.cmp(&key_hi)
         }),
     }
 
-    let lines_width = total.total_lines.to_string().len();
+    let lines_width = total.size.to_string().len();
     let copies_width = total.copies.to_string().len();
+    let column_widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            data.iter()
+                .map(|row| row.columns[i].len())
+                .chain([column.heading.len(), column.total.len()])
+                .max()
+                .unwrap()
+        })
+        .collect();
+    let extra_columns = |values: &mut dyn Iterator<Item = &str>, is_heading: bool| {
+        let mut line = String::new();
+        for ((column, width), value) in columns.iter().zip(&column_widths).zip(values) {
+            match (column.align, is_heading) {
+                (Align::Left, _) | (Align::Right, true) => {
+                    let _ = write!(line, "{:<1$}  ", value, width);
+                }
+                (Align::Right, false) => {
+                    let _ = write!(line, "{:>1$}  ", value, width);
+                }
+            }
+        }
+        line
+    };
 
     let stdout = io::stdout();
     let mut handle = stdout.lock();
     let _ = writeln!(
         handle,
-        "  Lines{0:1$}           Copies{0:2$}          Function name",
-        "", lines_width, copies_width,
+        "  {0:<1$}{2:<3$}{4}{5}",
+        headings.size,
+        lines_width + 16,
+        headings.copies,
+        copies_width + 16,
+        extra_columns(
+            &mut columns.iter().map(|column| column.heading.as_str()),
+            true,
+        ),
+        headings.name,
     );
+    let underlines: Vec<String> = columns
+        .iter()
+        .map(|column| underline(&column.heading))
+        .collect();
     let _ = writeln!(
         handle,
-        "  -----{0:1$}           ------{0:2$}          -------------",
-        "", lines_width, copies_width,
+        "  {0:<1$}{2:<3$}{4}{5}",
+        underline(headings.size),
+        lines_width + 16,
+        underline(headings.copies),
+        copies_width + 16,
+        extra_columns(&mut underlines.iter().map(String::as_str), true),
+        underline(headings.name),
     );
     let _ = writeln!(
         handle,
-        "  {0:1$}                {2:3$}                (TOTAL)",
-        total.total_lines, lines_width, total.copies, copies_width,
+        "  {0:1$}                {2:3$}                {4}(TOTAL)",
+        total.size,
+        lines_width,
+        total.copies,
+        copies_width,
+        extra_columns(
+            &mut columns.iter().map(|column| column.total.as_str()),
+            false,
+        ),
     );
     let mut cumul_lines = 0;
     let mut cumul_copies = 0;
@@ -73,18 +581,77 @@ pub(crate) fn print(
         )
     };
     for row in data {
-        if function_filter.map_or(true, |ff| ff.is_match(&row.0)) {
+        if filter.map_or(true, |ff| ff.is_match(&row.name)) {
             let _ = writeln!(
                 handle,
-                "  {0:1$} {2:<14} {3:4$} {5:<14} {6}",
-                row.1.total_lines,
+                "  {0:1$} {2:<14} {3:4$} {5:<14} {6}{7}",
+                row.size,
                 lines_width,
-                perc(row.1.total_lines, &mut cumul_lines, total.total_lines),
-                row.1.copies,
+                perc(row.size, &mut cumul_lines, total.size),
+                row.copies,
                 copies_width,
-                perc(row.1.copies, &mut cumul_copies, total.copies),
-                row.0,
+                perc(row.copies, &mut cumul_copies, total.copies),
+                extra_columns(&mut row.columns.iter().map(String::as_str), false),
+                row.name,
             );
         }
     }
 }
+
+fn underline(heading: &str) -> String {
+    "-".repeat(heading.len())
+}
+
+pub(crate) fn print_composition(composition: &Composition) {
+    let sections = composition.sections();
+
+    let mut total = Section { lines: 0, bytes: 0 };
+    for (_name, section) in &sections {
+        total.lines += section.lines;
+        total.bytes += section.bytes;
+    }
+
+    let lines_width = total.lines.to_string().len();
+    let bytes_width = total.bytes.to_string().len();
+
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    let _ = writeln!(
+        handle,
+        "  Lines{0:1$}      Bytes{0:2$}      Section",
+        "", lines_width, bytes_width,
+    );
+    let _ = writeln!(
+        handle,
+        "  -----{0:1$}      -----{0:2$}      -------",
+        "", lines_width, bytes_width,
+    );
+    let _ = writeln!(
+        handle,
+        "  {0:1$}           {2:3$}           (TOTAL)",
+        total.lines, lines_width, total.bytes, bytes_width,
+    );
+    // Every section is empty when the input was only bitcode.
+    let perc = |m, n| {
+        let perc = if n == 0 {
+            0f64
+        } else {
+            m as f64 / n as f64 * 100f64
+        };
+        format!("({:5.1}%)", perc)
+    };
+    for (name, section) in sections {
+        let _ = writeln!(
+            handle,
+            "  {0:1$} {2:<9} {3:4$} {5:<9} {6}",
+            section.lines,
+            lines_width,
+            perc(section.lines, total.lines),
+            section.bytes,
+            bytes_width,
+            perc(section.bytes, total.bytes),
+            name,
+        );
+    }
+    let _ = writeln!(handle);
+}
@@ -0,0 +1,196 @@
+use crate::count::demangle_symbol;
+use crate::name::split_trait_impl;
+use std::collections::{BTreeSet as Set, HashMap as Map};
+
+/// Trait object vtables, and the lines of the functions they point to such as
+/// `{{vtable.shim}}` and `call_once` shims, grouped by concrete type and
+/// trait.
+#[derive(Default)]
+pub struct Vtables {
+    tables: Vec<Vtable>,
+    // Mangled symbol of every function referenced by a vtable, with the
+    // number of lines of its definition once it has been seen.
+    function_lines: Map<String, usize>,
+    // Number of vtables whose debug name has been resolved. Metadata ids are
+    // only unique within one module, so they are resolved module by module.
+    resolved: usize,
+    // !0 = !DIGlobalVariableExpression(var: !1, expr: !DIExpression())
+    debug_expressions: Map<String, String>,
+    // !1 = distinct !DIGlobalVariable(name: "<a::Sq as a::Shape>::{vtable}", ...)
+    debug_names: Map<String, String>,
+}
+
+struct Vtable {
+    debug_id: Option<String>,
+    debug_name: Option<String>,
+    functions: Vec<String>,
+}
+
+pub struct VtableGroup {
+    pub vtables: usize,
+    pub lines: usize,
+}
+
+impl Vtables {
+    // @vtable.0 = private constant <{ [24 x i8], ptr }> <{ [24 x i8] c"...", ptr @_RNvXCs6xxl1lAjzDf_1aNtB2_2SqNtB2_5Shape4area }>, align 8, !dbg !24
+    pub fn record_vtable(&mut self, line: &str) {
+        let (initializer, debug_id) = match line.rsplit_once(", !dbg ") {
+            Some((initializer, debug_id)) => (initializer, Some(debug_id.trim().to_owned())),
+            None => (line, None),
+        };
+
+        let mut functions = Vec::new();
+        for (i, _) in initializer.match_indices("ptr @") {
+            let symbol = &initializer[i + "ptr @".len()..];
+            let symbol = if let Some(quoted) = symbol.strip_prefix('"') {
+                quoted.find('"').map(|end| &quoted[..end])
+            } else {
+                symbol.find([',', ' ', '}']).map(|end| &symbol[..end])
+            };
+            if let Some(symbol) = symbol {
+                self.function_lines.entry(symbol.to_owned()).or_insert(0);
+                functions.push(symbol.to_owned());
+            }
+        }
+
+        self.tables.push(Vtable {
+            debug_id,
+            debug_name: None,
+            functions,
+        });
+    }
+
+    pub fn record_function(&mut self, mangled: &str, lines: usize) {
+        if let Some(function_lines) = self.function_lines.get_mut(mangled) {
+            *function_lines += lines;
+        }
+    }
+
+    pub fn record_metadata(&mut self, line: &str) {
+        let Some((id, rest)) = line.split_once(" = ") else {
+            return;
+        };
+        if let Some(rest) = rest.strip_prefix("!DIGlobalVariableExpression(var: ") {
+            if let Some(var) = rest.split([',', ')']).next() {
+                self.debug_expressions.insert(id.to_owned(), var.to_owned());
+            }
+        } else if let Some(rest) = rest.strip_prefix("distinct !DIGlobalVariable(name: \"") {
+            if let Some(name) = rest.split('"').next() {
+                if name.ends_with("::{vtable}") {
+                    self.debug_names.insert(id.to_owned(), name.to_owned());
+                }
+            }
+        }
+    }
+
+    pub fn finish_module(&mut self) {
+        for vtable in &mut self.tables[self.resolved..] {
+            vtable.debug_name = vtable
+                .debug_id
+                .take()
+                .and_then(|id| self.debug_expressions.get(&id))
+                .and_then(|var| self.debug_names.get(var))
+                .cloned();
+        }
+        self.resolved = self.tables.len();
+        self.debug_expressions.clear();
+        self.debug_names.clear();
+    }
+
+    pub fn merge(&mut self, other: Vtables) {
+        self.tables.extend(other.tables);
+        self.resolved = self.tables.len();
+        for (symbol, lines) in other.function_lines {
+            *self.function_lines.entry(symbol).or_insert(0) += lines;
+        }
+    }
+
+    pub fn groups(&self) -> Map<String, VtableGroup> {
+        let mut functions = Map::<String, (usize, Set<&str>)>::new();
+        for vtable in &self.tables {
+            let group = functions.entry(vtable.group_key()).or_default();
+            group.0 += 1;
+            group.1.extend(vtable.functions.iter().map(String::as_str));
+        }
+
+        functions
+            .into_iter()
+            .map(|(key, (vtables, functions))| {
+                let lines = functions
+                    .into_iter()
+                    .map(|function| self.function_lines[function])
+                    .sum();
+                (key, VtableGroup { vtables, lines })
+            })
+            .collect()
+    }
+}
+
+impl Vtable {
+    fn group_key(&self) -> String {
+        if let Some(debug_name) = &self.debug_name {
+            return debug_name.trim_end_matches("::{vtable}").to_owned();
+        }
+
+        let mut dropped_type = None;
+        for function in &self.functions {
+            let name = demangle_symbol(function);
+            if let Some((self_ty, trait_path, _method)) = split_trait_impl(&name) {
+                return format!("<{} as {}>", self_ty, trait_path);
+            } else if let Some(shim) = name.strip_suffix("{{vtable.shim}}") {
+                // Legacy mangling does not preserve the self type of shims:
+                // core::ops::function::FnOnce::call_once{{vtable.shim}}
+                if let Some((trait_path, _method)) = shim.rsplit_once("::") {
+                    return format!("<_ as {}>", trait_path);
+                }
+            } else if let Some(ty) = name.strip_prefix("core::ptr::drop_in_place<") {
+                dropped_type = ty.strip_suffix('>').map(str::to_owned);
+            }
+        }
+
+        match dropped_type {
+            Some(ty) => format!("<{} as _>", ty),
+            None => "(unknown)".to_owned(),
+        }
+    }
+}
+
+#[test]
+fn test_vtable_groups() {
+    const DROP: &str = "_ZN4core3ptr30drop_in_place$LT$a..Square$GT$17h0123456789abcdefE";
+    const SQUARE_AREA: &str = "_RNvXCs6xxl1lAjzDf_1aNtB2_6SquareNtB2_5Shape4area";
+    const CIRCLE_AREA: &str =
+        "_ZN38_$LT$a..Circle$u20$as$u20$a..Shape$GT$4area17hfedcba9876543210E";
+
+    let mut vtables = Vtables::default();
+    vtables.record_vtable(&format!(
+        "@vtable.0 = private constant <{{ ptr, [16 x i8], ptr }}> <{{ ptr @{}, [16 x i8] c\"...\", ptr @{} }}>, align 8, !dbg !24",
+        DROP, SQUARE_AREA,
+    ));
+    vtables.record_function(DROP, 4);
+    vtables.record_function(SQUARE_AREA, 7);
+    vtables.record_metadata("!24 = !DIGlobalVariableExpression(var: !25, expr: !DIExpression())");
+    vtables.record_metadata(
+        "!25 = distinct !DIGlobalVariable(name: \"<a::Square as a::Shape>::{vtable}\", scope: !2)",
+    );
+    vtables.finish_module();
+
+    // A second module, without debug info, in which !24 means nothing.
+    let mut other = Vtables::default();
+    for i in 0..2 {
+        other.record_vtable(&format!(
+            "@vtable.{} = private constant <{{ ptr, [16 x i8], ptr }}> <{{ ptr null, [16 x i8] c\"...\", ptr @\"{}\" }}>, align 8",
+            i, CIRCLE_AREA,
+        ));
+    }
+    other.record_function(CIRCLE_AREA, 9);
+    other.finish_module();
+    vtables.merge(other);
+
+    let groups = vtables.groups();
+    assert_eq!(groups.len(), 2);
+    let square = &groups["<a::Square as a::Shape>"];
+    assert_eq!((square.vtables, square.lines), (1, 11));
+    let circle = &groups["<a::Circle as a::Shape>"];
+    assert_eq!((circle.vtables, circle.lines), (2, 9));
+}
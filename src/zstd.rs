@@ -0,0 +1,89 @@
+use ruzstd::decoding::errors::{FrameDecoderError, ReadFrameHeaderError};
+use ruzstd::decoding::{BlockDecodingStrategy, FrameDecoder};
+use std::io::{self, BufRead, Read};
+
+/// Decompresses every frame of a zstd stream. Ruzstd's `StreamingDecoder`
+/// stops after the first one, but tools like pzstd, or concatenating .zst
+/// files, produce several.
+pub struct ZstdDecoder<R> {
+    source: R,
+    decoder: FrameDecoder,
+}
+
+impl<R: BufRead> ZstdDecoder<R> {
+    pub fn new(source: R) -> io::Result<Self> {
+        let mut decoder = ZstdDecoder {
+            source,
+            decoder: FrameDecoder::new(),
+        };
+        decoder.next_frame()?;
+        Ok(decoder)
+    }
+
+    // Reads the header of the next frame, skipping over skippable frames.
+    // Returns false at the end of the input.
+    fn next_frame(&mut self) -> io::Result<bool> {
+        loop {
+            if self.source.fill_buf()?.is_empty() {
+                return Ok(false);
+            }
+            match self.decoder.reset(&mut self.source) {
+                Ok(()) => return Ok(true),
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                    length,
+                    ..
+                })) => {
+                    let mut frame = (&mut self.source).take(u64::from(length));
+                    io::copy(&mut frame, &mut io::sink())?;
+                }
+                Err(err) => return Err(invalid_data(err)),
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Read for ZstdDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            while self.decoder.can_collect() < buf.len() && !self.decoder.is_finished() {
+                let needed = buf.len() - self.decoder.can_collect();
+                self.decoder
+                    .decode_blocks(&mut self.source, BlockDecodingStrategy::UptoBytes(needed))
+                    .map_err(invalid_data)?;
+            }
+            let n = self.decoder.read(buf)?;
+            if n > 0 || !self.next_frame()? {
+                return Ok(n);
+            }
+        }
+    }
+}
+
+fn invalid_data(err: FrameDecoderError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+#[test]
+fn test_multiple_frames() {
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    let first = "define void @f() {\n  ret void\n}\n";
+    let second = "define void @g() {\n  ret void\n}\n";
+    let mut compressed = compress_to_vec(first.as_bytes(), CompressionLevel::Fastest);
+    // A skippable frame between the two.
+    compressed.extend([0x50, 0x2a, 0x4d, 0x18, 3, 0, 0, 0, 1, 2, 3]);
+    compressed.extend(compress_to_vec(
+        second.as_bytes(),
+        CompressionLevel::Fastest,
+    ));
+
+    let mut decompressed = String::new();
+    ZstdDecoder::new(compressed.as_slice())
+        .unwrap()
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(decompressed, first.to_owned() + second);
+}